@@ -1,48 +1,26 @@
-use std::fs;
+use std::{collections::HashSet, env, fs, path::Path};
 
-use pest::{iterators::Pair, Parser};
-use pest_derive::Parser;
+#[path = "src/fingerprint.rs"]
+mod fingerprint;
 
-use std::hash::{Hash, Hasher};
-use std::collections::hash_map::DefaultHasher;
+#[path = "src/workflows.rs"]
+mod workflows;
 
-#[derive(Parser)]
-#[grammar = "src/puzzles/day19grammar.pest"]
-struct Day19Parser;
+use workflows::{Branch, Destination};
 
 fn write_day19_programs() {
-    fn parse_workflow(f_prefix: &str, p: Pair<'_, Rule>) -> String {
-        let mut tokens = p.into_inner();
-        let id = tokens.next().unwrap().as_str();
-
-        let rules = tokens
-            .map(|rule| {
-                let buf: Vec<Pair<'_, Rule>> = rule.into_inner().collect();
-                let mut cond = "true".to_string();
-                let mut dest = "".to_string();
-                if buf.len() == 2
-                /* condition and destination */
-                {
-                    cond = buf[0].as_str().to_string();
-                    dest = buf[1].as_str().to_string();
-                } else
-                /* just dest */
-                {
-                    dest = buf[0].as_str().to_string();
-                }
-
-                if dest == "A" {
-                    dest = "true".to_string();
-                } else if dest == "R" {
-                    dest = "false".to_string();
-                } else {
-                    dest = format!("{f_prefix}_{dest}(_x, _m, _a, _s)");
-                }
-
-                if cond == "true" {
-                    return format!("return {dest};");
-                } else {
-                    return format!("if _{cond} {{ return {dest}; }}");
+    fn generate_workflow(f_prefix: &str, id: &str, branches: &[Branch]) -> String {
+        let rules = branches
+            .iter()
+            .map(|branch| {
+                let dest = match &branch.destination {
+                    Destination::Accept => "true".to_string(),
+                    Destination::Reject => "false".to_string(),
+                    Destination::Workflow(next) => format!("{f_prefix}_{next}(_x, _m, _a, _s)"),
+                };
+                match &branch.condition {
+                    Some(cond) => format!("if _{cond} {{ return {dest}; }}"),
+                    None => format!("return {dest};"),
                 }
             })
             .collect::<Vec<String>>()
@@ -51,41 +29,102 @@ fn write_day19_programs() {
         return format!("fn {f_prefix}_{id}(_x: u32, _m: u32, _a: u32, _s: u32) -> bool {{ {rules} }}");
     }
 
-    fn parse_part(p: Pair<'_, Rule>) -> String {
-        format!("[{}]", p.into_inner().map(|it| it.into_inner().next().unwrap().as_str().to_string()).collect::<Vec<String>>().join(", "))
+    /// whether the generated functions would compile: the interpreter copes with the rest at runtime
+    fn check_workflows(workflows: &workflows::Workflows) -> Result<(), String> {
+        if !workflows.contains_key("in") {
+            return Err("there is no in workflow".to_string());
+        }
+        let mut ids: Vec<&String> = workflows.keys().collect();
+        ids.sort();
+        for id in ids {
+            let branches = &workflows[id];
+            if branches.last().map_or(true, |branch| branch.condition.is_some()) {
+                return Err(format!("workflow {} has no fallback rule", id));
+            }
+            for branch in branches {
+                if let Destination::Workflow(next) = &branch.destination {
+                    if !workflows.contains_key(next) {
+                        return Err(format!("workflow {} goes to {}, which is not defined", id, next));
+                    }
+                }
+            }
+        }
+        return Ok(());
     }
 
-    
-    let contents = ["day19_ex", "day19"].map(|filename| {
-        let input = fs::read_to_string(format!("input/{}", filename)).expect("Unable to read file");
-        
-        let mut hasher = DefaultHasher::new();
-        input.hash(&mut hasher);
-        let f_prefix = format!("_{:x}", hasher.finish());
+    fn generate_part(part: &[u32; 4]) -> String {
+        format!("[{}]", part.map(|rating| rating.to_string()).join(", "))
+    }
 
-        let mut program = Day19Parser::parse(Rule::Program, &input)
-            .unwrap()
-            .next()
-            .unwrap()
-            .into_inner();
+    println!("cargo:rerun-if-changed=src/puzzles/day19grammar.pest");
+    println!("cargo:rerun-if-changed=src/fingerprint.rs");
+    println!("cargo:rerun-if-changed=src/workflows.rs");
+    // a directory is rescanned as a whole, so new inputs get picked up too
+    println!("cargo:rerun-if-changed=input");
 
-        let workflows = program.next().unwrap().into_inner().map(|it| parse_workflow(&f_prefix, it)).collect::<Vec<String>>().join("\n");
-        
-        let parts = format!("fn {f_prefix}_get_parts() -> Vec<[u32; 4]> {{\n    Vec::from([\n        {}\n    ])\n}}", program.next().unwrap().into_inner().map(parse_part).collect::<Vec<String>>().join(",\n        "));
+    // without inputs there is nothing to compile, and day 19 interprets the workflows instead
+    let mut filenames = match fs::read_dir("input") {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|filename| filename.starts_with("day19"))
+            .collect::<Vec<String>>(),
+        Err(e) => {
+            println!("cargo:warning=no day 19 workflows compiled, cannot read input/: {}", e);
+            Vec::new()
+        }
+    };
+    filenames.sort();
+
+    let mut contents = Vec::<(String, String)>::new();
+    let mut prefixes = HashSet::<String>::new();
+    for filename in filenames {
+        let path = format!("input/{}", filename);
+        println!("cargo:rerun-if-changed={}", path);
+        let Ok(input) = fs::read_to_string(&path) else {
+            println!("cargo:warning=skipping {}, unable to read it", path);
+            continue;
+        };
+
+        let f_prefix = format!("_{:016x}", fingerprint::fingerprint(&input));
+        // identical inputs would define the same functions twice
+        if !prefixes.insert(f_prefix.clone()) {
+            continue;
+        }
+
+        let (workflows, parts) = match workflows::parse_program(&input) {
+            Ok(program) => program,
+            Err(e) => {
+                println!("cargo:warning=skipping {}, it does not parse: {}", path, e.replace("\n", " "));
+                continue;
+            }
+        };
+
+        // the same simplifications as when interpreting, so that both run the same workflows
+        let workflows = workflows::optimize(&workflows);
+        if let Err(e) = check_workflows(&workflows) {
+            println!("cargo:warning=skipping {}, {}", path, e);
+            continue;
+        }
+        let mut ids: Vec<&String> = workflows.keys().collect();
+        ids.sort();
+        let workflows = ids.into_iter().map(|id| generate_workflow(&f_prefix, id, &workflows[id])).collect::<Vec<String>>().join("\n");
         
+        let parts = format!("fn {f_prefix}_get_parts() -> Vec<[u32; 4]> {{\n    Vec::from([\n        {}\n    ])\n}}", parts.iter().map(generate_part).collect::<Vec<String>>().join(",\n        "));
         
-        return (
+        contents.push((
             format!("(\"{f_prefix}\".to_string(), ({f_prefix}_get_parts as (fn() -> Vec<[u32; 4]>), {f_prefix}_in as (fn(u32, u32, u32, u32) -> bool)))"),
             format!("/* auto-generated from: {filename} */\n{workflows}\n\n{parts}\n")
-        );
-    }).into_iter().collect::<Vec<(String, String)>>();
+        ));
+    }
     
     let index = format!("use std::collections::HashMap;\npub fn get_index() -> HashMap<String, (fn() -> Vec<[u32; 4]>, fn(u32, u32, u32, u32) -> bool)> {{ return HashMap::from([{}])}}", contents.iter().map(|it| (&it.0).to_string()).into_iter().collect::<Vec<String>>().join(", "));
     
-    let dest = "src/puzzles/day19/day19_workflows.rs";
+    let dest = Path::new(&env::var("OUT_DIR").unwrap()).join("day19_workflows.rs");
     fs::write(&dest, format!("{index}\n\n{}", contents.into_iter().map(|it| (&it.1).to_string()).into_iter().collect::<Vec<String>>().join("\n\n"))).unwrap();
 }
 
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
     write_day19_programs();
 }
\ No newline at end of file
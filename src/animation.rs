@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::iter;
+use std::path::Path;
+
+/// a grid of characters, as printed in the puzzles
+pub type Frame = Vec<Vec<char>>;
+
+/// RGB colour of every grid character
+#[derive(Debug, Clone)]
+pub struct Palette {
+    colors: HashMap<char, [u8; 3]>,
+    /// for characters that are not in <colors>
+    default: [u8; 3],
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {
+            colors: HashMap::from([
+                ('.', [0x10, 0x10, 0x20]),
+                ('#', [0x80, 0x80, 0x80]),
+                ('O', [0xff, 0x99, 0x00]),
+                ('$', [0x30, 0x60, 0xc0]),
+            ]),
+            default: [0xff, 0xff, 0xff],
+        }
+    }
+}
+
+impl Palette {
+    /// "O=ff9900,#=808080" overrides the default colours of O and #
+    pub fn parse(spec: &str) -> Result<Palette, String> {
+        let mut palette = Palette::default();
+        for entry in spec.split(",").filter(|e| !e.is_empty()) {
+            let mut chars = entry.chars();
+            let (Some(c), Some('='), hex) = (chars.next(), chars.next(), chars.as_str()) else {
+                return Err(format!("bad palette entry '{}', expected <char>=<rrggbb>", entry));
+            };
+            let Ok(rgb) = u32::from_str_radix(hex, 16) else {
+                return Err(format!("bad colour '{}' for '{}', expected rrggbb", hex, c));
+            };
+            if hex.len() != 6 {
+                return Err(format!("bad colour '{}' for '{}', expected rrggbb", hex, c));
+            }
+            palette.colors.insert(c, [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]);
+        }
+        return Ok(palette);
+    }
+
+    pub fn color(&self, c: char) -> [u8; 3] {
+        *self.colors.get(&c).unwrap_or(&self.default)
+    }
+}
+
+/// frames may not all have the same size, the canvas fits the largest
+fn canvas_size(frames: &[Frame]) -> (usize, usize) {
+    let width = frames.iter().flat_map(|f| f.iter().map(|line| line.len())).max().unwrap_or(0);
+    let height = frames.iter().map(|f| f.len()).max().unwrap_or(0);
+    return (width, height);
+}
+
+/// one value per pixel, <scale> pixels per cell; missing cells are drawn as spaces
+fn rasterize<T: Copy>(frame: &Frame, width: usize, height: usize, scale: usize, pixel: impl Fn(char) -> T) -> Vec<T> {
+    let mut pixels = Vec::with_capacity(width * height * scale * scale);
+    for y in 0..height * scale {
+        let line = frame.get(y / scale);
+        for x in 0..width * scale {
+            let c = line.and_then(|l| l.get(x / scale)).copied().unwrap_or(' ');
+            pixels.push(pixel(c));
+        }
+    }
+    return pixels;
+}
+
+/// looping GIF, <delay> in hundredths of a second between frames
+pub fn write_gif(frames: &[Frame], palette: &Palette, scale: usize, delay: u16, path: &Path) -> io::Result<()> {
+    let (width, height) = canvas_size(frames);
+
+    // GIFs are indexed, so every character gets a slot in the global palette (blanks included)
+    let mut indices = HashMap::<char, u8>::new();
+    let mut global_palette = Vec::<u8>::new();
+    for c in iter::once(&' ').chain(frames.iter().flatten().flatten()) {
+        if !indices.contains_key(c) {
+            if indices.len() == 256 {
+                return Err(io::Error::other("more than 256 different characters in the frames"));
+            }
+            indices.insert(*c, indices.len() as u8);
+            global_palette.extend(palette.color(*c));
+        }
+    }
+
+    // GIF dimensions are 16 bits, wrapping would leave the pixels and the size out of step
+    let too_large = |_| io::Error::other(format!(
+        "{}x{} pixels is too large for a GIF, lower --scale or write PNG frames instead",
+        width * scale,
+        height * scale
+    ));
+    let (w, h) = (u16::try_from(width * scale).map_err(too_large)?, u16::try_from(height * scale).map_err(too_large)?);
+    let mut encoder = gif::Encoder::new(BufWriter::new(File::create(path)?), w, h, &global_palette)
+        .map_err(io::Error::other)?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+    for frame in frames {
+        let pixels = rasterize(frame, width, height, scale, |c| indices[&c]);
+        let mut gif_frame = gif::Frame::from_indexed_pixels(w, h, &pixels, None);
+        gif_frame.delay = delay;
+        encoder.write_frame(&gif_frame).map_err(io::Error::other)?;
+    }
+    return Ok(());
+}
+
+/// one PNG per frame in <dir>, named so that they sort in order
+pub fn write_png_frames(frames: &[Frame], palette: &Palette, scale: usize, dir: &Path) -> io::Result<()> {
+    let (width, height) = canvas_size(frames);
+    // PNG dimensions are 32 bits, checked before anything is written
+    let too_large = |_| io::Error::other(format!(
+        "{}x{} pixels is too large for a PNG, lower --scale",
+        width * scale,
+        height * scale
+    ));
+    let (w, h) = (u32::try_from(width * scale).map_err(too_large)?, u32::try_from(height * scale).map_err(too_large)?);
+    fs::create_dir_all(dir)?;
+
+    for (i, frame) in frames.iter().enumerate() {
+        let file = File::create(dir.join(format!("frame_{:05}.png", i)))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), w, h);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let pixels: Vec<u8> = rasterize(frame, width, height, scale, |c| palette.color(c)).concat();
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&pixels))
+            .map_err(io::Error::other)?;
+    }
+    return Ok(());
+}
+
+/// write the frames as a GIF if <path> ends in .gif, as a directory of PNGs otherwise
+pub fn export(frames: &[Frame], path: &Path) {
+    let args = crate::args();
+    let palette = match &args.palette {
+        Some(spec) => Palette::parse(spec).unwrap_or_else(|e| panic!("{}", e)),
+        None => Palette::default(),
+    };
+    let result = if path.extension().is_some_and(|ext| ext == "gif") {
+        write_gif(frames, &palette, args.scale, args.frame_delay, path)
+    } else {
+        write_png_frames(frames, &palette, args.scale, path)
+    };
+    result.expect("Unable to write animation");
+}
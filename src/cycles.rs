@@ -0,0 +1,195 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+/// where the sequence initial, step(initial), step(step(initial))... starts repeating
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    /// index of the first state that is part of the cycle
+    pub start: u64,
+    pub length: u64,
+}
+
+impl Cycle {
+    /// earliest step with the same state as step <target>
+    pub fn equivalent_step(&self, target: u64) -> u64 {
+        if target < self.start {
+            return target;
+        }
+        // f(n) = f(init_size+((n-init_size)%cycle_size)
+        return self.start + (target - self.start) % self.length;
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// remember every state (once), the cycle is found as soon as it closes
+    #[default]
+    HashMap,
+    /// Brent's algorithm, constant memory but some states get computed more than once
+    Brent,
+}
+
+/// look for a cycle, giving up after <limit> steps
+pub fn find_cycle<S, F>(initial: &S, step: F, mode: Mode, limit: Option<u64>) -> Option<Cycle>
+where
+    S: Hash + Eq + Clone,
+    F: FnMut(&S) -> S,
+{
+    match mode {
+        Mode::HashMap => find_cycle_hash_map(initial, step, limit).map(|(cycle, _)| cycle),
+        Mode::Brent => find_cycle_brent(initial, step, limit),
+    }
+}
+
+/// also returns every state up to the end of the first lap of the cycle
+fn find_cycle_hash_map<S, F>(initial: &S, mut step: F, limit: Option<u64>) -> Option<(Cycle, Vec<S>)>
+where
+    S: Hash + Eq + Clone,
+    F: FnMut(&S) -> S,
+{
+    // states can be large, so they are kept only once, in <states>, and looked up by hash
+    let hasher = RandomState::new();
+    let mut seen = HashMap::<u64, Vec<u64>>::new();
+    let mut states = Vec::from([initial.clone()]);
+    seen.insert(hasher.hash_one(initial), Vec::from([0]));
+
+    let mut i = 0;
+    while limit.map_or(true, |limit| i < limit) {
+        i += 1;
+        let next = step(&states[states.len() - 1]);
+        let hash = hasher.hash_one(&next);
+        let same_hash = seen.entry(hash).or_default();
+        if let Some(start) = same_hash.iter().find(|j| states[**j as usize] == next) {
+            let cycle = Cycle {
+                start: *start,
+                length: i - start,
+            };
+            return Some((cycle, states));
+        }
+        same_hash.push(i);
+        states.push(next);
+    }
+    return None;
+}
+
+fn find_cycle_brent<S, F>(initial: &S, mut step: F, limit: Option<u64>) -> Option<Cycle>
+where
+    S: Eq + Clone,
+    F: FnMut(&S) -> S,
+{
+    // the hare runs ahead, the tortoise teleports to it at every power of two
+    let mut power = 1;
+    let mut length = 1;
+    let mut tortoise = initial.clone();
+    let mut hare = step(initial);
+    let mut hare_steps = 1;
+    while tortoise != hare {
+        if limit.is_some_and(|limit| hare_steps >= limit) {
+            return None;
+        }
+        if power == length {
+            tortoise = hare.clone();
+            power *= 2;
+            length = 0;
+        }
+        hare = step(&hare);
+        hare_steps += 1;
+        length += 1;
+    }
+
+    // with the hare <length> steps ahead, both meet at the start of the cycle
+    let mut tortoise = initial.clone();
+    let mut hare = initial.clone();
+    for _ in 0..length {
+        hare = step(&hare);
+    }
+    let mut start = 0;
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        start += 1;
+    }
+    return Some(Cycle { start, length });
+}
+
+/// state after <target> steps, skipping ahead once a cycle is found, and that cycle if there was one
+/// None if no cycle was found within <limit> steps and <target> is further than that
+pub fn state_at<S, F>(initial: S, mut step: F, target: u64, mode: Mode, limit: Option<u64>) -> Option<(S, Option<Cycle>)>
+where
+    S: Hash + Eq + Clone,
+    F: FnMut(&S) -> S,
+{
+    // no point looking further than the target
+    let search_limit = Some(limit.map_or(target, |limit| limit.min(target)));
+
+    match mode {
+        Mode::HashMap => {
+            if let Some((cycle, mut states)) = find_cycle_hash_map(&initial, &mut step, search_limit) {
+                let state = states.swap_remove(cycle.equivalent_step(target) as usize);
+                return Some((state, Some(cycle)));
+            }
+        }
+        Mode::Brent => {
+            if let Some(cycle) = find_cycle(&initial, &mut step, Mode::Brent, search_limit) {
+                let state = simulate(initial, &mut step, cycle.equivalent_step(target));
+                return Some((state, Some(cycle)));
+            }
+        }
+    }
+
+    if limit.is_some_and(|limit| limit < target) {
+        return None;
+    }
+    return Some((simulate(initial, step, target), None));
+}
+
+/// state after <steps> steps, the hard way
+pub fn simulate<S, F>(initial: S, mut step: F, steps: u64) -> S
+where
+    F: FnMut(&S) -> S,
+{
+    let mut state = initial;
+    for _ in 0..steps {
+        state = step(&state);
+    }
+    return state;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(x: &u64) -> u64 {
+        (x * x + 1) % 1009
+    }
+
+    #[test]
+    fn modes_agree_with_simulation() {
+        for initial in [0, 2, 500] {
+            let by_hash_map = find_cycle(&initial, step, Mode::HashMap, None).unwrap();
+            let by_brent = find_cycle(&initial, step, Mode::Brent, None).unwrap();
+            assert_eq!(by_hash_map, by_brent);
+
+            for target in [0, 1, 10, 1000, 1_000_000_007] {
+                for mode in [Mode::HashMap, Mode::Brent] {
+                    let (state, _) = state_at(initial, step, target, mode, None).unwrap();
+                    let expected = simulate(initial, step, by_hash_map.equivalent_step(target));
+                    assert_eq!(state, expected, "{:?} from {} to {}", mode, initial, target);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn limit_is_respected() {
+        let cycle = find_cycle(&0, step, Mode::HashMap, None).unwrap();
+        let steps_needed = cycle.start + cycle.length;
+        for mode in [Mode::HashMap, Mode::Brent] {
+            assert_eq!(find_cycle(&0, step, mode, Some(1)), None);
+            assert!(state_at(0, step, 1_000_000, mode, Some(1)).is_none());
+            assert_eq!(state_at(0, step, 1, mode, Some(1)).unwrap().0, step(&0));
+        }
+        assert!(find_cycle(&0, step, Mode::HashMap, Some(steps_needed)).is_some());
+    }
+}
@@ -0,0 +1,47 @@
+/*
+identifies puzzle inputs, the same way in build.rs (which includes this file) and at runtime
+so it must not depend on anything that could change between builds, unlike DefaultHasher
+
+fingerprint = 64-bit FNV-1a of the UTF-8 bytes of the input after:
+- every "\r\n" is replaced by "\n"
+- trailing newlines are removed, then exactly one "\n" is added back
+*/
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn normalize(input: &str) -> String {
+    let mut normalized = input.replace("\r\n", "\n");
+    normalized.truncate(normalized.trim_end_matches('\n').len());
+    normalized.push('\n');
+    return normalized;
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    return hash;
+}
+
+pub fn fingerprint(input: &str) -> u64 {
+    fnv1a(normalize(input).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_and_normalized() {
+        // reference values of FNV-1a
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+
+        assert_eq!(fingerprint("in{A}\r\n\r\n"), fingerprint("in{A}\n"));
+        assert_eq!(fingerprint("in{A}"), fingerprint("in{A}\n"));
+        assert_ne!(fingerprint("in{A}\n\nx"), fingerprint("in{A}\nx"));
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::VecDeque;
+
+/*
+flood fills over grids stored as rows, with (x, y) cells like in the renderer
+both keep their own work list instead of recursing, so the size of the area does not matter
+*/
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Connectivity {
+    /// up, down, left and right
+    #[default]
+    Four,
+    /// diagonals too
+    Eight,
+}
+
+impl Connectivity {
+    fn offsets(&self) -> &'static [(isize, isize)] {
+        match self {
+            Connectivity::Four => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Connectivity::Eight => &[(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)],
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// one cell at a time, breadth first
+    Queue,
+    /// whole horizontal runs at a time
+    #[default]
+    Scanline,
+}
+
+/// flood_fill or scanline_fill depending on <mode>
+pub fn fill<T: Clone>(
+    grid: &mut [Vec<T>],
+    start: (usize, usize),
+    mode: Mode,
+    connectivity: Connectivity,
+    passable: impl Fn(&T) -> bool,
+    value: T,
+) -> usize {
+    match mode {
+        Mode::Queue => flood_fill(grid, start, connectivity, passable, value),
+        Mode::Scanline => scanline_fill(grid, start, connectivity, passable, value),
+    }
+}
+
+fn neighbour<T>(grid: &[Vec<T>], (x, y): (usize, usize), (dx, dy): (isize, isize)) -> Option<(usize, usize)> {
+    let x = x.checked_add_signed(dx)?;
+    let y = y.checked_add_signed(dy)?;
+    if y < grid.len() && x < grid[y].len() {
+        return Some((x, y));
+    }
+    return None;
+}
+
+/// sets every cell reachable from <start> through cells where <passable> holds to <value>,
+/// breadth first; returns how many cells were filled
+pub fn flood_fill<T: Clone>(
+    grid: &mut [Vec<T>],
+    start: (usize, usize),
+    connectivity: Connectivity,
+    passable: impl Fn(&T) -> bool,
+    value: T,
+) -> usize {
+    if start.1 >= grid.len() || start.0 >= grid[start.1].len() || !passable(&grid[start.1][start.0]) {
+        return 0;
+    }
+    // <value> may well be passable itself, so filled cells are tracked separately
+    let mut seen: Vec<Vec<bool>> = grid.iter().map(|line| vec![false; line.len()]).collect();
+    let mut queue = VecDeque::from([start]);
+    seen[start.1][start.0] = true;
+    let mut filled = 0;
+
+    while let Some((x, y)) = queue.pop_front() {
+        grid[y][x] = value.clone();
+        filled += 1;
+        for offset in connectivity.offsets() {
+            if let Some((nx, ny)) = neighbour(grid, (x, y), *offset) {
+                if !seen[ny][nx] && passable(&grid[ny][nx]) {
+                    seen[ny][nx] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+    }
+    return filled;
+}
+
+/// same result as flood_fill, but a whole horizontal run of cells at a time,
+/// which keeps the work list much shorter on large open areas
+pub fn scanline_fill<T: Clone>(
+    grid: &mut [Vec<T>],
+    start: (usize, usize),
+    connectivity: Connectivity,
+    passable: impl Fn(&T) -> bool,
+    value: T,
+) -> usize {
+    let mut seen: Vec<Vec<bool>> = grid.iter().map(|line| vec![false; line.len()]).collect();
+    let fillable = |grid: &[Vec<T>], seen: &Vec<Vec<bool>>, (x, y): (usize, usize)| {
+        y < grid.len() && x < grid[y].len() && !seen[y][x] && passable(&grid[y][x])
+    };
+    let mut seeds = Vec::from([start]);
+    let mut filled = 0;
+
+    while let Some((x, y)) = seeds.pop() {
+        if !fillable(grid, &seen, (x, y)) {
+            continue;
+        }
+        let mut left = x;
+        while left > 0 && fillable(grid, &seen, (left - 1, y)) {
+            left -= 1;
+        }
+        let mut right = x;
+        while fillable(grid, &seen, (right + 1, y)) {
+            right += 1;
+        }
+        for cx in left..=right {
+            seen[y][cx] = true;
+            grid[y][cx] = value.clone();
+        }
+        filled += right - left + 1;
+
+        // diagonal neighbours reach one cell past each end of the run
+        let (lo, hi) = match connectivity {
+            Connectivity::Four => (left, right),
+            Connectivity::Eight => (left.saturating_sub(1), right + 1),
+        };
+        for ny in [y.checked_sub(1), Some(y + 1)].into_iter().flatten() {
+            // one seed per run of fillable cells in the neighbouring row
+            let mut in_run = false;
+            for nx in lo..=hi {
+                let open = fillable(grid, &seen, (nx, ny));
+                if open && !in_run {
+                    seeds.push((nx, ny));
+                }
+                in_run = open;
+            }
+        }
+    }
+    return filled;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Vec<Vec<char>> {
+        input.lines().map(|line| line.chars().collect()).collect()
+    }
+
+    #[test]
+    fn connectivity() {
+        let input = "\
+.#...
+#..#.
+..#.#
+.#...";
+        for (connectivity, expected) in [(Connectivity::Four, 1), (Connectivity::Eight, 14)] {
+            let mut grid = parse(input);
+            assert_eq!(flood_fill(&mut grid, (0, 0), connectivity, |c| *c == '.', 'x'), expected);
+            let mut scanned = parse(input);
+            assert_eq!(scanline_fill(&mut scanned, (0, 0), connectivity, |c| *c == '.', 'x'), expected);
+            assert_eq!(grid, scanned);
+        }
+        // starting on a wall fills nothing, and row and column 0 are reachable from elsewhere
+        let mut grid = parse(input);
+        assert_eq!(flood_fill(&mut grid, (1, 0), Connectivity::Four, |c| *c == '.', 'x'), 0);
+        assert_eq!(flood_fill(&mut grid, (4, 0), Connectivity::Four, |c| *c == '.', 'x'), 9);
+        assert_eq!(grid[3][0], 'x');
+        assert_eq!(grid[3][2], '.');
+    }
+
+    #[test]
+    fn large_area() {
+        // a long winding corridor, which a recursive fill would follow one frame per cell
+        let size = 500;
+        let mut grid: Vec<Vec<bool>> = (0..size)
+            .map(|y| (0..size).map(|x| y % 2 == 0 || (y % 4 == 1 && x == size - 1) || (y % 4 == 3 && x == 0)).collect())
+            .collect();
+        let open = grid.iter().flatten().filter(|c| **c).count();
+        let mut scanned = grid.clone();
+        assert_eq!(flood_fill(&mut grid, (0, 0), Connectivity::Four, |c| *c, false), open);
+        assert_eq!(scanline_fill(&mut scanned, (0, 0), Connectivity::Four, |c| *c, false), open);
+    }
+}
@@ -1,20 +1,241 @@
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
 use std::collections::HashMap;
 use std::fs;
+use std::sync::OnceLock;
 
+mod animation;
+mod cycles;
+mod fingerprint;
+mod flood;
+mod polygon;
 mod puzzles;
+mod render;
+mod workflows;
 
 use crate::puzzles::*;
 use util::*;
 
 #[derive(Parser)]
-struct CliArgs {
+pub struct CliArgs {
     puzzle_id: String,
-    input_path: std::path::PathBuf,
+    #[arg(required_unless_present = "inline")]
+    input_path: Option<std::path::PathBuf>,
+
+    /// use this text as the puzzle input instead of reading a file
+    #[arg(long)]
+    inline: Option<String>,
+
+    /// print the ranked hands and how each one was scored
+    #[arg(long, help_heading = "day 7")]
+    pub explain: bool,
+
+    /// also print the complete galaxy graph in DOT format
+    #[arg(long, help_heading = "day 11")]
+    pub graph: bool,
+
+    /// how many rows or columns each empty one expands to (default depends on the part)
+    #[arg(long, help_heading = "day 11")]
+    pub expansion: Option<u64>,
+
+    /// how to measure the distance between galaxies
+    #[arg(long, value_enum, default_value_t, help_heading = "day 11")]
+    pub metric: day11::Metric,
+
+    /// count by building the full search tree (slow, small records only)
+    #[arg(long, help_heading = "day 12")]
+    pub reference: bool,
+
+    /// list every arrangement of each record
+    #[arg(long, help_heading = "day 12")]
+    pub arrangements: bool,
+
+    /// print this many uniformly random arrangements of each record
+    #[arg(long, help_heading = "day 12")]
+    pub sample: Option<u32>,
+
+    /// which record (numbered from 1) to inspect, by default the first one for
+    /// --tree-dot and all of them for --tree-stats
+    #[arg(long, help_heading = "day 12")]
+    pub record: Option<usize>,
+
+    /// write the search tree of the chosen record to this DOT file
+    #[arg(long, help_heading = "day 12")]
+    pub tree_dot: Option<std::path::PathBuf>,
+
+    /// print node and pruning statistics for the search tree of the chosen record
+    #[arg(long, help_heading = "day 12")]
+    pub tree_stats: bool,
+
+    /// directions to tilt in for each cycle, among N, W, S and E
+    #[arg(long, default_value = "NWSE", help_heading = "day 14")]
+    pub tilts: String,
+
+    /// how many cycles to run
+    #[arg(long, default_value_t = 1_000_000_000, help_heading = "day 14")]
+    pub cycles: u64,
+
+    /// give up if no state repeats within this many cycles
+    #[arg(long, help_heading = "day 14")]
+    pub search_limit: Option<u64>,
+
+    /// how to look for repeating states
+    #[arg(long, value_enum, default_value_t, help_heading = "day 14")]
+    pub cycle_mode: cycles::Mode,
+
+    /// print the north load after every cycle
+    #[arg(long, help_heading = "day 14")]
+    pub every_step: bool,
+
+    /// print the boxes after every step, and which labels shared a box
+    #[arg(long, help_heading = "day 15")]
+    pub trace: bool,
+
+    /// like --trace, but only after steps on this label
+    #[arg(long, help_heading = "day 15")]
+    pub trace_label: Option<String>,
+
+    /// like --trace, but only after steps on labels that go in this box
+    #[arg(long, help_heading = "day 15")]
+    pub trace_box: Option<usize>,
+
+    /// also fill the lagoon cell by cell and print that result instead (slow, small inputs only)
+    #[arg(long, help_heading = "day 18")]
+    pub raster: bool,
+
+    /// draw the trench, in its colours, to this SVG file
+    #[arg(long, help_heading = "day 18")]
+    pub svg: Option<std::path::PathBuf>,
+
+    /// label the corners in the --svg drawing
+    #[arg(long, help_heading = "day 18")]
+    pub svg_labels: bool,
+
+    /// how --raster floods the lagoon
+    #[arg(long, value_enum, default_value_t, help_heading = "day 18")]
+    pub flood_mode: flood::Mode,
+
+    /// neighbours reached by --raster's flood, both stay inside since the trench has no diagonal gaps
+    #[arg(long, value_enum, default_value_t, help_heading = "day 18")]
+    pub flood_connectivity: flood::Connectivity,
+
+    /// write the workflow graph to this DOT file
+    #[arg(long, help_heading = "day 19")]
+    pub workflow_dot: Option<std::path::PathBuf>,
+
+    /// run the workflows generated at build time or interpret them; either one prints its timing
+    #[arg(long, value_enum, default_value_t, help_heading = "day 19")]
+    pub engine: day19::Engine,
+
+    /// day 11: list each galaxy's nearest neighbour and the farthest pair;
+    /// day 18: describe the trench polygon;
+    /// day 19: look for unreachable, looping, dead or constant workflows
+    #[arg(long, help_heading = "days 11, 18 and 19")]
+    pub report: bool,
+
+    /// days 14 and 18: write the grid states to a GIF if this ends in .gif, to a directory of PNGs otherwise
+    #[arg(long, help_heading = "days 14, 15 and 18: drawing")]
+    pub animate: Option<std::path::PathBuf>,
+
+    /// colours for --animate and --render, as in "O=ff9900,#=808080"
+    #[arg(long, help_heading = "days 14, 15 and 18: drawing")]
+    pub palette: Option<String>,
+
+    /// pixels per grid cell for --animate
+    #[arg(long, default_value_t = 4, help_heading = "days 14, 15 and 18: drawing")]
+    pub scale: usize,
+
+    /// hundredths of a second between GIF frames, or between --live redraws
+    #[arg(long, default_value_t = 10, help_heading = "days 14, 15 and 18: drawing")]
+    pub frame_delay: u16,
+
+    /// draw the grid in the terminal (day 18: part 2 only with --raster)
+    #[arg(long, help_heading = "days 14, 15 and 18: drawing")]
+    pub render: bool,
+
+    /// with --render, redraw every step of the simulation in place
+    #[arg(long, help_heading = "days 14, 15 and 18: drawing")]
+    pub live: bool,
+
+    /// with --render, cells to highlight, as in "3,4;10,2"
+    #[arg(long, help_heading = "days 14, 15 and 18: drawing")]
+    pub highlight: Option<String>,
+
+    /// with --render, top left cell of the viewport, as in "40,0"
+    #[arg(long, help_heading = "days 14, 15 and 18: drawing")]
+    pub viewport: Option<String>,
+
+    /// with --render, columns and rows to show instead of the terminal size, as in "80,24"
+    #[arg(long, help_heading = "days 14, 15 and 18: drawing")]
+    pub viewport_size: Option<String>,
+}
+
+/// days that read each option, any other day rejects it rather than silently ignoring it
+const OPTION_DAYS: &[(&str, &[u32])] = &[
+    ("explain", &[7]),
+    ("graph", &[11]),
+    ("expansion", &[11]),
+    ("metric", &[11]),
+    ("reference", &[12]),
+    ("arrangements", &[12]),
+    ("sample", &[12]),
+    ("record", &[12]),
+    ("tree_dot", &[12]),
+    ("tree_stats", &[12]),
+    ("tilts", &[14]),
+    ("cycles", &[14]),
+    ("search_limit", &[14]),
+    ("cycle_mode", &[14]),
+    ("every_step", &[14]),
+    ("trace", &[15]),
+    ("trace_label", &[15]),
+    ("trace_box", &[15]),
+    ("raster", &[18]),
+    ("svg", &[18]),
+    ("svg_labels", &[18]),
+    ("flood_mode", &[18]),
+    ("flood_connectivity", &[18]),
+    ("workflow_dot", &[19]),
+    ("engine", &[19]),
+    ("report", &[11, 18, 19]),
+    ("animate", &[14, 18]),
+    ("palette", &[14, 15, 18]),
+    ("scale", &[14, 18]),
+    ("frame_delay", &[14, 15, 18]),
+    ("render", &[14, 15, 18]),
+    ("live", &[14, 15, 18]),
+    ("highlight", &[14, 15, 18]),
+    ("viewport", &[14, 15, 18]),
+    ("viewport_size", &[14, 15, 18]),
+];
+
+/// exits with a usage error if an option given on the command line is not read by the day of <puzzle_id>
+fn check_options(matches: &ArgMatches, puzzle_id: &str) {
+    let Some(Ok(day)) = puzzle_id.split('-').next().map(|day| day.parse::<u32>()) else {
+        return;
+    };
+    for (id, days) in OPTION_DAYS {
+        if matches.value_source(id) == Some(ValueSource::CommandLine) && !days.contains(&day) {
+            let plural = if days.len() > 1 { "s" } else { "" };
+            let days = days.iter().map(|d| d.to_string()).collect::<Vec<String>>().join(", ");
+            let message = format!("--{} is not used by {}, only by day{} {}", id.replace('_', "-"), puzzle_id, plural, days);
+            CliArgs::command().error(ErrorKind::ArgumentConflict, message).exit();
+        }
+    }
+}
+
+static ARGS: OnceLock<CliArgs> = OnceLock::new();
+
+/// command line arguments, for puzzles that take extra options
+pub fn args() -> &'static CliArgs {
+    ARGS.get().expect("arguments are parsed in main")
 }
 
 fn main() {
-    let args = CliArgs::parse();
+    let matches = CliArgs::command().get_matches();
+    let args = ARGS.get_or_init(|| CliArgs::from_arg_matches(&matches).unwrap_or_else(|e| e.exit()));
+    check_options(&matches, &args.puzzle_id);
 
     let mut puzzles: HashMap<String, fn(String)> = HashMap::new();
     register_puzzles_for_days!(7);
@@ -29,8 +250,29 @@ fn main() {
     register_puzzle!(18, 1);
     register_puzzle!(18, 2);
     register_puzzle!(19, 1);
+    register_puzzle!(19, 2);
 
-    let input = fs::read_to_string(&args.input_path).expect("Unable to read file");
+    let input = match (&args.inline, &args.input_path) {
+        (Some(text), _) => format!("{}\n", text.trim_end()),
+        (None, Some(path)) => fs::read_to_string(path).expect("Unable to read file"),
+        (None, None) => unreachable!("clap requires one of them"),
+    };
 
     puzzles[&args.puzzle_id](input);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_option_has_days() {
+        let positional = ["puzzle_id", "input_path", "inline", "help"];
+        for arg in CliArgs::command().get_arguments() {
+            let id = arg.get_id().as_str();
+            if !positional.contains(&id) {
+                assert!(OPTION_DAYS.iter().any(|(option, _)| *option == id), "--{} is missing from OPTION_DAYS", id);
+            }
+        }
+    }
+}
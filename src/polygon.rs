@@ -0,0 +1,185 @@
+/*
+polygons whose edges are all parallel to an axis, on integer coordinates
+y grows downwards, like rows in the puzzle grids
+*/
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Orientation {
+    /// as seen on screen, with y growing downwards
+    Clockwise,
+    CounterClockwise,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Containment {
+    Inside,
+    Boundary,
+    Outside,
+}
+
+/// closed loop through its vertices, the last one connecting back to the first
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    vertices: Vec<Point>,
+}
+
+impl Polygon {
+    pub fn new(vertices: Vec<Point>) -> Result<Polygon, String> {
+        let polygon = Polygon { vertices };
+        for (a, b) in polygon.edges() {
+            if a.x != b.x && a.y != b.y {
+                return Err(format!("edge from {:?} to {:?} is not parallel to an axis", a, b));
+            }
+        }
+        return Ok(polygon);
+    }
+
+    pub fn vertices(&self) -> &[Point] {
+        &self.vertices
+    }
+
+    /// (start, end) of every edge, in order
+    pub fn edges(&self) -> impl Iterator<Item = (&Point, &Point)> {
+        self.vertices.iter().zip(self.vertices.iter().cycle().skip(1))
+    }
+
+    /// shoelace formula, positive when clockwise on screen
+    fn double_signed_area(&self) -> i64 {
+        self.edges()
+            .map(|(a, b)| a.x as i64 * b.y as i64 - b.x as i64 * a.y as i64)
+            .sum()
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        if self.double_signed_area() >= 0 {
+            Orientation::Clockwise
+        } else {
+            Orientation::CounterClockwise
+        }
+    }
+
+    /// area enclosed by the line through the vertices
+    pub fn area(&self) -> u64 {
+        // rectilinear polygons on integer coordinates always have an integer area
+        (self.double_signed_area().abs() / 2) as u64
+    }
+
+    /// which for a rectilinear polygon is also the number of lattice points on the boundary
+    pub fn perimeter(&self) -> u64 {
+        self.edges()
+            .map(|(a, b)| ((a.x - b.x).abs() + (a.y - b.y).abs()) as u64)
+            .sum()
+    }
+
+    /// lattice points strictly inside, by Pick's theorem (A = I + B/2 - 1)
+    /// None when the polygon is too degenerate for it, e.g. a line going back on itself
+    pub fn interior_lattice_points(&self) -> Option<u64> {
+        let interior = self.area() as i64 + 1 - self.perimeter() as i64 / 2;
+        return u64::try_from(interior).ok();
+    }
+
+    pub fn contains(&self, p: &Point) -> Containment {
+        let mut crossings = 0;
+        for (a, b) in self.edges() {
+            let (min_x, max_x) = (a.x.min(b.x), a.x.max(b.x));
+            let (min_y, max_y) = (a.y.min(b.y), a.y.max(b.y));
+            if min_x <= p.x && p.x <= max_x && min_y <= p.y && p.y <= max_y {
+                return Containment::Boundary;
+            }
+            // cast a ray to the right, counting vertical edges it crosses;
+            // half-open ranges so that a ray through a vertex counts once
+            if a.x == b.x && a.x > p.x && min_y <= p.y && p.y < max_y {
+                crossings += 1;
+            }
+        }
+        if crossings % 2 == 1 {
+            Containment::Inside
+        } else {
+            Containment::Outside
+        }
+    }
+
+    /// whether the boundary touches itself anywhere but between consecutive edges
+    pub fn is_self_intersecting(&self) -> bool {
+        let edges: Vec<(&Point, &Point)> = self.edges().filter(|(a, b)| a != b).collect();
+        let n = edges.len();
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let (a, b) = edges[i];
+                let (c, d) = edges[j];
+                let adjacent = j == i + 1 || (i == 0 && j == n - 1);
+                if adjacent {
+                    // consecutive edges share a vertex, they only overlap when going back on themselves
+                    let (first, second) = if j == i + 1 { ((a, b), (c, d)) } else { ((c, d), (a, b)) };
+                    let going = (first.1.x - first.0.x).signum() + 2 * (first.1.y - first.0.y).signum();
+                    let coming_back = (second.0.x - second.1.x).signum() + 2 * (second.0.y - second.1.y).signum();
+                    if going == coming_back {
+                        return true;
+                    }
+                } else if boxes_overlap((a, b), (c, d)) {
+                    return true;
+                }
+            }
+        }
+        return false;
+    }
+}
+
+/// axis-parallel segments intersect exactly when their bounding boxes do
+fn boxes_overlap((a, b): (&Point, &Point), (c, d): (&Point, &Point)) -> bool {
+    a.x.min(b.x) <= c.x.max(d.x)
+        && c.x.min(d.x) <= a.x.max(b.x)
+        && a.y.min(b.y) <= c.y.max(d.y)
+        && c.y.min(d.y) <= a.y.max(b.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(points: &[(i32, i32)]) -> Polygon {
+        Polygon::new(points.iter().map(|(x, y)| Point { x: *x, y: *y }).collect()).unwrap()
+    }
+
+    #[test]
+    fn l_shape() {
+        // ###
+        // #.#
+        // #.###
+        // #...#
+        // #####
+        let l = polygon(&[(0, 0), (2, 0), (2, 2), (4, 2), (4, 4), (0, 4)]);
+        assert_eq!(l.orientation(), Orientation::Clockwise);
+        assert_eq!(l.area(), 12);
+        assert_eq!(l.perimeter(), 16);
+        assert_eq!(l.interior_lattice_points(), Some(5));
+        assert_eq!(l.contains(&Point { x: 1, y: 1 }), Containment::Inside);
+        assert_eq!(l.contains(&Point { x: 3, y: 3 }), Containment::Inside);
+        assert_eq!(l.contains(&Point { x: 3, y: 1 }), Containment::Outside);
+        assert_eq!(l.contains(&Point { x: 5, y: 2 }), Containment::Outside);
+        assert_eq!(l.contains(&Point { x: 3, y: 2 }), Containment::Boundary);
+        assert!(!l.is_self_intersecting());
+
+        let reversed = polygon(&[(0, 4), (4, 4), (4, 2), (2, 2), (2, 0), (0, 0)]);
+        assert_eq!(reversed.orientation(), Orientation::CounterClockwise);
+        assert_eq!(reversed.area(), 12);
+    }
+
+    #[test]
+    fn self_intersections() {
+        let figure_eight = polygon(&[(0, 0), (2, 0), (2, 4), (4, 4), (4, 2), (0, 2)]);
+        assert!(figure_eight.is_self_intersecting());
+        let backtrack = polygon(&[(0, 0), (4, 0), (2, 0), (2, 2), (0, 2)]);
+        assert!(backtrack.is_self_intersecting());
+        let there_and_back = polygon(&[(0, 0), (2, 0)]);
+        assert!(there_and_back.is_self_intersecting());
+        assert_eq!(there_and_back.interior_lattice_points(), None);
+        assert!(Polygon::new(Vec::from([Point { x: 0, y: 0 }, Point { x: 1, y: 1 }])).is_err());
+    }
+}
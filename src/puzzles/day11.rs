@@ -1,4 +1,4 @@
-use petgraph::{graph::Graph, Undirected};
+use petgraph::{dot::Dot, graph::Graph, Undirected};
 use std::collections::{HashMap, HashSet};
 
 #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
@@ -8,12 +8,23 @@ struct Star {
     y: u64,
 }
 
+#[derive(clap::ValueEnum, PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub enum Metric {
+    #[default]
+    Manhattan,
+    Chebyshev,
+    Euclidean,
+}
+
 impl Star {
-    fn distance(&self, other: &Star) -> u64 {
-        u64::try_from(
-            (self.x as i64 - other.x as i64).abs() + (self.y as i64 - other.y as i64).abs(),
-        )
-        .unwrap()
+    fn distance(&self, other: &Star, metric: Metric) -> f64 {
+        let dx = (self.x as i64 - other.x as i64).abs() as f64;
+        let dy = (self.y as i64 - other.y as i64).abs() as f64;
+        match metric {
+            Metric::Manhattan => dx + dy,
+            Metric::Chebyshev => dx.max(dy),
+            Metric::Euclidean => (dx * dx + dy * dy).sqrt(),
+        }
     }
 }
 
@@ -42,10 +53,16 @@ fn parse_empty_rows(input: &str) -> HashSet<u64> {
     return rows;
 }
 
-fn parse_stars(
-    input: String,
-    increase_function: fn(u64, &mut u64, &HashSet<u64>),
-) -> HashSet<Star> {
+/// <expansion>: how many rows or columns each empty one turns into
+fn parse_stars(input: String, expansion: u64) -> HashSet<Star> {
+    fn increase_index_to_next_nonempty(real_i: u64, fake_i: &mut u64, empty: &HashSet<u64>, expansion: u64) {
+        if empty.contains(&real_i) {
+            *fake_i += expansion;
+        } else {
+            *fake_i += 1;
+        }
+    }
+
     let empty_rows = parse_empty_rows(&input);
     let empty_cols = parse_empty_cols(&input);
 
@@ -65,19 +82,20 @@ fn parse_stars(
                     y: fake_y,
                 });
             }
-            increase_function(real_x, &mut fake_x, &empty_cols);
+            increase_index_to_next_nonempty(real_x, &mut fake_x, &empty_cols, expansion);
             real_x += 1;
         }
-        increase_function(real_y, &mut fake_y, &empty_rows);
+        increase_index_to_next_nonempty(real_y, &mut fake_y, &empty_rows, expansion);
         real_y += 1;
     }
     return stars;
 }
 
-fn build_graph(stars: HashSet<Star>) -> (Graph<Star, u64, Undirected>, u64) {
+/// complete graph of the stars, only useful for visualization
+fn build_graph(stars: &HashSet<Star>, metric: Metric) -> Graph<Star, f64, Undirected> {
     let mut stars_buf = stars.clone();
 
-    let mut graph = Graph::<Star, u64, Undirected>::new_undirected();
+    let mut graph = Graph::<Star, f64, Undirected>::new_undirected();
 
     let mut star_map = HashMap::new();
 
@@ -88,49 +106,132 @@ fn build_graph(stars: HashSet<Star>) -> (Graph<Star, u64, Undirected>, u64) {
         star_map.insert(star, graph.add_node(**star));
     }
 
-    let mut edge_sum = 0;
     for star in stars.iter() {
         stars_buf.remove(&star);
         for other_star in &stars_buf {
-            let distance = star.distance(&other_star);
+            let distance = star.distance(&other_star, metric);
             graph.add_edge(star_map[&star], star_map[&other_star], distance);
-            edge_sum += distance;
         }
     }
-    return (graph, edge_sum);
+    return graph;
 }
 
-pub fn p1(input: String) {
-    fn _increase_index_to_next_nonempty(real_i: u64, fake_i: &mut u64, empty: &HashSet<u64>) {
-        if empty.contains(&real_i) {
-            *fake_i += 1;
+/// sum of |a - b| over all pairs, in O(n log n)
+fn axis_distance_sum(mut coords: Vec<i64>) -> u64 {
+    coords.sort_unstable();
+    // once sorted, each coordinate is at least as large as all the ones before it
+    let mut prefix_sum: i64 = 0;
+    let mut total: u64 = 0;
+    for (i, c) in coords.iter().enumerate() {
+        total += ((i as i64) * c - prefix_sum) as u64;
+        prefix_sum += c;
+    }
+    return total;
+}
+
+/// sum of the Manhattan distances between every pair of stars
+fn sum_manhattan_distances(stars: &HashSet<Star>) -> u64 {
+    // the Manhattan distance splits into independent per-axis distances
+    return axis_distance_sum(stars.iter().map(|s| s.x as i64).collect())
+        + axis_distance_sum(stars.iter().map(|s| s.y as i64).collect());
+}
+
+/// sum of the Chebyshev distances between every pair of stars
+fn sum_chebyshev_distances(stars: &HashSet<Star>) -> u64 {
+    // rotating by 45 degrees turns max(|dx|, |dy|) into (|du| + |dv|) / 2
+    return (axis_distance_sum(stars.iter().map(|s| s.x as i64 + s.y as i64).collect())
+        + axis_distance_sum(stars.iter().map(|s| s.x as i64 - s.y as i64).collect()))
+        / 2;
+}
+
+/// sum of the Euclidean distances between every pair of stars, no shortcut there
+fn sum_euclidean_distances(stars: &HashSet<Star>) -> f64 {
+    let stars: Vec<&Star> = stars.iter().collect();
+    let mut total = 0.0;
+    for (i, star) in stars.iter().enumerate() {
+        for other_star in &stars[i + 1..] {
+            total += star.distance(other_star, Metric::Euclidean);
         }
-        *fake_i += 1;
     }
+    return total;
+}
 
-    let stars = parse_stars(input.trim().to_string(), _increase_index_to_next_nonempty);
-    //println!("{:#?}", stars);
-    let (_graph, distances) = build_graph(stars);
+/// nearest neighbour of every star, and the two stars farthest apart
+fn print_neighbours(stars: &HashSet<Star>, metric: Metric) {
+    let mut sorted_stars = stars.iter().collect::<Vec<&Star>>();
+    sorted_stars.sort_by(|a, b| a.id.cmp(&b.id));
 
-    //println!("{:?}", Dot::new(&_graph));
-    //println!("{:?}", graph);
+    let mut farthest: Option<(u32, u32, f64)> = None;
+    for star in sorted_stars.iter() {
+        let mut nearest: Option<(u32, f64)> = None;
+        for other_star in sorted_stars.iter().filter(|o| o.id != star.id) {
+            let distance = star.distance(other_star, metric);
+            if nearest.map_or(true, |(_, d)| distance < d) {
+                nearest = Some((other_star.id, distance));
+            }
+            if farthest.map_or(true, |(_, _, d)| distance > d) {
+                farthest = Some((star.id, other_star.id, distance));
+            }
+        }
+        match nearest {
+            Some((id, distance)) => println!("{}: nearest is {} at {}", star.id, id, distance),
+            None => println!("{}: alone", star.id),
+        }
+    }
+    if let Some((a, b, distance)) = farthest {
+        println!("farthest pair: {} and {} at {}", a, b, distance);
+    }
+}
 
-    println!("{}", distances);
+/// print the answer, and the graph or neighbours if they were asked for
+fn report(input: String, default_expansion: u64) {
+    let args = crate::args();
+    let stars = parse_stars(input.trim().to_string(), args.expansion.unwrap_or(default_expansion));
+
+    if args.graph {
+        println!("{:?}", Dot::new(&build_graph(&stars, args.metric)));
+    }
+    if args.report {
+        print_neighbours(&stars, args.metric);
+    }
+    match args.metric {
+        Metric::Manhattan => println!("{}", sum_manhattan_distances(&stars)),
+        Metric::Chebyshev => println!("{}", sum_chebyshev_distances(&stars)),
+        Metric::Euclidean => println!("{}", sum_euclidean_distances(&stars)),
+    }
+}
+
+pub fn p1(input: String) {
+    report(input, 2);
 }
 
 pub fn p2(input: String) {
-    fn _increase_index_to_next_nonempty(real_i: u64, fake_i: &mut u64, empty: &HashSet<u64>) {
-        if empty.contains(&real_i) {
-            *fake_i += 1_000_000;
-        } else {
-            *fake_i += 1;
+    report(input, 1_000_000);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brute_force_sum(stars: &HashSet<Star>, metric: Metric) -> u64 {
+        let stars: Vec<&Star> = stars.iter().collect();
+        let mut sum = 0.0;
+        for (i, a) in stars.iter().enumerate() {
+            for b in &stars[i + 1..] {
+                sum += a.distance(b, metric);
+            }
         }
+        return sum as u64;
     }
 
-    let stars = parse_stars(input.trim().to_string(), _increase_index_to_next_nonempty);
-    let (_graph, distances) = build_graph(stars);
-    // seems I didn't need to actually build the graph in the end..
-    // oh well, might have been useful had p2 been different
-
-    println!("{}", distances);
+    #[test]
+    fn sums_match_pairwise_distances() {
+        let input = std::fs::read_to_string("input/day11_ex").unwrap();
+        for expansion in [1, 2, 10, 100] {
+            let stars = parse_stars(input.clone(), expansion);
+            assert_eq!(sum_manhattan_distances(&stars), brute_force_sum(&stars, Metric::Manhattan), "expansion {}", expansion);
+            assert_eq!(sum_chebyshev_distances(&stars), brute_force_sum(&stars, Metric::Chebyshev), "expansion {}", expansion);
+        }
+        assert_eq!(sum_manhattan_distances(&parse_stars(input, 2)), 374);
+    }
 }
@@ -1,13 +1,15 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     fmt::{Display, Formatter},
 };
 
+use petgraph::dot::{Config, Dot};
 use petgraph::visit::IntoNodeReferences;
 use petgraph::{graph::Graph, stable_graph::NodeIndex, Directed};
 
 use pest::Parser;
 use pest_derive::Parser;
+use rand::Rng;
 
 #[derive(Parser)]
 #[grammar = "src/puzzles/day12grammar.pest"]
@@ -75,9 +77,8 @@ fn parse_input(input: &str) -> Vec<Record> {
 /// Returns whether a block (a string of #, ? and .) could answer the given GSI
 /// this is for early pruning to avoid unnecessary computation
 fn block_is_candidate(input: &str, gsi: &VecDeque<u32>) -> bool {
-    if input.contains("?")
-    {
-        return true;
+    if let Some(first_unknown) = input.find("?") {
+        return prefix_is_candidate(&input[..first_unknown], &input[first_unknown..], gsi);
     }
     let blocks: Vec<_> = input.split(".").filter(|b| *b != "").collect();
     if blocks.len() != gsi.len() {
@@ -108,6 +109,34 @@ fn block_is_candidate(input: &str, gsi: &VecDeque<u32>) -> bool {
     // }
 }
 
+/// whether a resolved <prefix> followed by a still unresolved <rest> could answer the given GSI
+fn prefix_is_candidate(prefix: &str, rest: &str, gsi: &VecDeque<u32>) -> bool {
+    let gsi: Vec<usize> = gsi.iter().map(|g| *g as usize).collect();
+    let mut runs: Vec<usize> = prefix.split(".").filter(|b| *b != "").map(|b| b.len()).collect();
+    // a run touching the first ? may still grow
+    let open_run = if prefix.ends_with("#") { runs.pop().unwrap() } else { 0 };
+
+    if runs.len() > gsi.len() || runs.iter().zip(gsi.iter()).any(|(run, group)| run != group) {
+        return false;
+    }
+    let next_group = runs.len();
+    if open_run > 0 && (next_group >= gsi.len() || open_run > gsi[next_group]) {
+        return false;
+    }
+
+    // what is left has to fit in the rest, with a . between groups
+    let remaining_groups = &gsi[next_group..];
+    let needed_springs = remaining_groups.iter().sum::<usize>() - open_run;
+    if rest.chars().filter(|c| *c != '.').count() < needed_springs {
+        return false;
+    }
+    let needed_length = needed_springs + remaining_groups.len().saturating_sub(1);
+    if rest.len() < needed_length {
+        return false;
+    }
+    return rest.matches("#").count() <= needed_springs;
+}
+
 fn build_tree(r: &Record) -> Graph<TreeNode, ()> {
     let mut tree = Graph::<TreeNode, (), Directed>::new();
 
@@ -161,54 +190,353 @@ fn build_tree(r: &Record) -> Graph<TreeNode, ()> {
     return tree;
 }
 
-pub fn p1(input: String) {
-    let records = parse_input(&input);
+fn count_tree_leaves(r: &Record) -> u64 {
+    let tree = build_tree(r);
 
-    let sum = records
-        .into_iter()
-        .map(|r| {
-            let tree = build_tree(&r);
-            //println!("{:?}", Dot::with_config(&tree, &[Config::EdgeNoLabel]));
+    return tree
+        .node_references()
+        .filter(|(_, node)| node.status == TreeNodeType::LEAF)
+        .count() as u64;
+}
 
-            let s = tree
-                .node_references()
-                .filter(|(_, node)| node.status == TreeNodeType::LEAF)
-                .count();
-            //println!("{}", s);
+/// how much work the search tree of a record took
+#[derive(Debug, Default)]
+struct TreeStats {
+    nodes: usize,
+    expanded: usize,
+    pruned: usize,
+    leaves: usize,
+    max_depth: usize,
+}
 
-            return s;
-        })
-        .sum::<usize>();
-    println!("{}", sum);
+fn compute_tree_stats(tree: &Graph<TreeNode, ()>) -> TreeStats {
+    let mut stats = TreeStats::default();
+    let Some(root) = tree.node_weights().next() else {
+        return stats;
+    };
+    // every level of the tree resolves exactly one '?'
+    let unknowns = root.map.matches('?').count();
+    for node in tree.node_weights() {
+        stats.nodes += 1;
+        match node.status {
+            TreeNodeType::CANDIDATE => stats.expanded += 1,
+            TreeNodeType::PRUNED => stats.pruned += 1,
+            TreeNodeType::LEAF => stats.leaves += 1,
+        }
+        stats.max_depth = stats.max_depth.max(unknowns - node.map.matches('?').count());
+    }
+    return stats;
+}
+
+fn tree_to_dot(tree: &Graph<TreeNode, ()>) -> String {
+    let node_attributes = |_, (_, node): (NodeIndex, &TreeNode)| {
+        let color = match node.status {
+            TreeNodeType::CANDIDATE => "lightblue",
+            TreeNodeType::LEAF => "palegreen",
+            TreeNodeType::PRUNED => "lightcoral",
+        };
+        format!("label = \"{}\" style = filled fillcolor = {}", node.map, color)
+    };
+    return format!(
+        "{:?}",
+        Dot::with_attr_getters(
+            tree,
+            &[Config::EdgeNoLabel, Config::NodeNoLabel],
+            &|_, _| String::new(),
+            &node_attributes,
+        )
+    );
+}
+
+/// write the search tree of the chosen record and print its statistics, if asked for
+/// without a chosen record, statistics are summed over all of them
+fn inspect_trees(records: &[Record]) {
+    let args = crate::args();
+    if args.tree_dot.is_none() && !args.tree_stats {
+        return;
+    }
+    // records are numbered from 1, like lines in the input
+    let chosen: Vec<&Record> = match args.record {
+        Some(n) => Vec::from([n
+            .checked_sub(1)
+            .and_then(|i| records.get(i))
+            .unwrap_or_else(|| panic!("there is no record {} (only {})", n, records.len()))]),
+        None if args.tree_dot.is_some() => records.iter().take(1).collect(),
+        None => records.iter().collect(),
+    };
+
+    let mut total = TreeStats::default();
+    for r in chosen.iter() {
+        let tree = build_tree(r);
+        if let Some(path) = &args.tree_dot {
+            std::fs::write(path, tree_to_dot(&tree)).expect("Unable to write file");
+        }
+        let stats = compute_tree_stats(&tree);
+        total.nodes += stats.nodes;
+        total.expanded += stats.expanded;
+        total.pruned += stats.pruned;
+        total.leaves += stats.leaves;
+        total.max_depth = total.max_depth.max(stats.max_depth);
+    }
+    if args.tree_stats {
+        let name = match chosen.as_slice() {
+            [r] => r.to_string(),
+            _ => format!("{} records", chosen.len()),
+        };
+        println!(
+            "{}: {} nodes, {} expanded, {} pruned, {} leaves, max depth {}",
+            name, total.nodes, total.expanded, total.pruned, total.leaves, total.max_depth
+        );
+    }
+}
+
+/// memoized number of arrangements for every (position, group index, current run length)
+struct ArrangementCounter {
+    map: Vec<u8>,
+    gsi: Vec<usize>,
+    memo: HashMap<(usize, usize, usize), u64>,
+}
+
+impl ArrangementCounter {
+    fn new(r: &Record) -> ArrangementCounter {
+        ArrangementCounter {
+            map: r.map.as_bytes().to_vec(),
+            gsi: r.gsi.iter().map(|g| *g as usize).collect(),
+            memo: HashMap::new(),
+        }
+    }
+
+    /// state after putting <c> (# or .) at <pos>, None if that breaks the record
+    fn step(&self, pos: usize, group: usize, run: usize, c: u8) -> Option<(usize, usize, usize)> {
+        if self.map[pos] != b'?' && self.map[pos] != c {
+            return None;
+        }
+        if c == b'#' {
+            // extend (or start) the current group
+            if group < self.gsi.len() && run < self.gsi[group] {
+                return Some((pos + 1, group, run + 1));
+            }
+            return None;
+        }
+        if run == 0 {
+            return Some((pos + 1, group, 0));
+        }
+        if run == self.gsi[group] {
+            // close the current group
+            return Some((pos + 1, group + 1, 0));
+        }
+        return None;
+    }
+
+    /// arrangements of map[pos..], given that groups before <group> are done
+    /// and the current run of # has length <run>
+    fn count(&mut self, pos: usize, group: usize, run: usize) -> u64 {
+        if pos == self.map.len() {
+            let done = if run == 0 {
+                group == self.gsi.len()
+            } else {
+                group == self.gsi.len() - 1 && run == self.gsi[group]
+            };
+            return done as u64;
+        }
+        if let Some(cached) = self.memo.get(&(pos, group, run)) {
+            return *cached;
+        }
+
+        let mut total = 0;
+        for c in [b'#', b'.'] {
+            if let Some((p, g, r)) = self.step(pos, group, run, c) {
+                total += self.count(p, g, r);
+            }
+        }
+
+        self.memo.insert((pos, group, run), total);
+        return total;
+    }
+
+    /// uniformly random arrangement, None if there are none
+    fn sample(&mut self, rng: &mut impl Rng) -> Option<String> {
+        let (mut pos, mut group, mut run) = (0, 0, 0);
+        if self.count(pos, group, run) == 0 {
+            return None;
+        }
+        let mut result = String::new();
+        while pos < self.map.len() {
+            // picking each character in proportion to the completions it allows keeps the draw uniform
+            let mut branches: Vec<(u8, (usize, usize, usize), u64)> = Vec::new();
+            for c in [b'#', b'.'] {
+                if let Some(next) = self.step(pos, group, run, c) {
+                    branches.push((c, next, self.count(next.0, next.1, next.2)));
+                }
+            }
+            let mut pick = rng.gen_range(0..branches.iter().map(|b| b.2).sum::<u64>());
+            for (c, next, weight) in branches {
+                if pick < weight {
+                    result.push(c as char);
+                    (pos, group, run) = next;
+                    break;
+                }
+                pick -= weight;
+            }
+        }
+        return Some(result);
+    }
+}
+
+/// lazily lists every arrangement of a record, never exploring a dead end
+struct Arrangements {
+    counter: ArrangementCounter,
+    stack: Vec<(usize, usize, usize, String)>,
+}
+
+impl Arrangements {
+    fn new(r: &Record) -> Arrangements {
+        let mut counter = ArrangementCounter::new(r);
+        let stack = if counter.count(0, 0, 0) > 0 {
+            Vec::from([(0, 0, 0, String::new())])
+        } else {
+            Vec::new()
+        };
+        Arrangements { counter, stack }
+    }
+}
+
+impl Iterator for Arrangements {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while let Some((pos, group, run, prefix)) = self.stack.pop() {
+            if pos == self.counter.map.len() {
+                return Some(prefix);
+            }
+            // pushed in reverse so that # comes out first
+            for c in [b'.', b'#'] {
+                if let Some((p, g, r)) = self.counter.step(pos, group, run, c) {
+                    if self.counter.count(p, g, r) > 0 {
+                        self.stack.push((p, g, r, format!("{}{}", prefix, c as char)));
+                    }
+                }
+            }
+        }
+        return None;
+    }
+}
+
+/// count the arrangements matching a record without enumerating them
+fn count_arrangements(r: &Record) -> u64 {
+    return ArrangementCounter::new(r).count(0, 0, 0);
+}
+
+/// print the arrangements and samples that were asked for on the command line
+fn show_arrangements(records: &[Record]) {
+    let args = crate::args();
+    let mut rng = rand::thread_rng();
+    for r in records {
+        if args.arrangements {
+            println!("{}:", r);
+            Arrangements::new(r).for_each(|a| println!("  {}", a));
+        }
+        if let Some(n) = args.sample {
+            println!("{} samples of {}:", n, r);
+            let mut counter = ArrangementCounter::new(r);
+            for _ in 0..n {
+                match counter.sample(&mut rng) {
+                    Some(a) => println!("  {}", a),
+                    None => break,
+                }
+            }
+        }
+    }
+}
+
+/// count with the DP, or with the full search tree when asked for (small records only)
+fn count_records(records: Vec<Record>) -> u64 {
+    show_arrangements(&records);
+    inspect_trees(&records);
+    let counter = if crate::args().reference {
+        count_tree_leaves
+    } else {
+        count_arrangements
+    };
+    return records.iter().map(counter).sum::<u64>();
+}
+
+pub fn p1(input: String) {
+    let records = parse_input(&input);
+    println!("{}", count_records(records));
+}
+
+/// the record five times over, with the copies of the map joined by an unknown spring
+fn unfold(r: Record) -> Record {
+    let blen = r.bricks.len() * 5;
+    let gsilen = r.gsi.len() * 5;
+    return Record {
+        map: vec![r.map; 5].join("?"),
+        bricks: r.bricks.into_iter().cycle().take(blen).collect(),
+        gsi: r.gsi.into_iter().cycle().take(gsilen).collect(),
+    };
 }
 
 pub fn p2(input: String) {
     let records = parse_input(&input);
+    let unfolded_records = records.into_iter().map(unfold);
+    println!("{}", count_records(unfolded_records.collect()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
 
-    let unfolded_records = records.into_iter().map(|r| {
-        let blen = r.bricks.len() * 5;
-        let gsilen = r.gsi.len() * 5;
-        Record {
-            map: r.map.repeat(5),
-            bricks: r.bricks.into_iter().cycle().take(blen).collect(),
-            gsi: r.gsi.into_iter().cycle().take(gsilen).collect(),
+    #[test]
+    fn dp_matches_search_tree() {
+        let input = std::fs::read_to_string("input/day12_ex").unwrap();
+        for r in parse_input(&input) {
+            assert_eq!(count_arrangements(&r), count_tree_leaves(&r), "{}", r);
         }
-    });
+    }
+
+    #[test]
+    fn unfolded_example() {
+        let input = std::fs::read_to_string("input/day12_ex").unwrap();
+        let unfolded: Vec<Record> = parse_input(&input).into_iter().map(unfold).collect();
+        assert_eq!(unfolded[0].map, "???.###????.###????.###????.###????.###");
+        assert_eq!(unfolded.iter().map(count_arrangements).sum::<u64>(), 525152);
+    }
+
+    #[test]
+    fn arrangements_match_records() {
+        let input = std::fs::read_to_string("input/day12_ex").unwrap();
+        for r in parse_input(&input) {
+            let arrangements: Vec<String> = Arrangements::new(&r).collect();
+            assert_eq!(arrangements.len() as u64, count_arrangements(&r), "{}", r);
+            for a in arrangements {
+                assert!(!a.contains('?') && block_is_candidate(&a, &r.gsi), "{} for {}", a, r);
+                assert!(a.chars().zip(r.map.chars()).all(|(c, m)| m == '?' || c == m), "{} for {}", a, r);
+            }
+        }
+    }
 
-    let sum = unfolded_records
-        .into_iter()
-        .map(|r| {
-            let tree = build_tree(&r);
-            //println!("{:?}", Dot::with_config(&tree, &[Config::EdgeNoLabel]));
-
-            let s = tree
-                .node_references()
-                .filter(|(_, node)| node.status == TreeNodeType::LEAF)
-                .count();
-            //println!("{}", s);
-
-            return s;
-        })
-        .sum::<usize>();
-    println!("{}", sum);
+    #[test]
+    fn samples_are_uniform() {
+        let r = parse_input("?###???????? 3,2,1\n").remove(0);
+        let arrangements: Vec<String> = Arrangements::new(&r).collect();
+        assert_eq!(arrangements.len(), 10);
+
+        let mut rng = StdRng::seed_from_u64(12);
+        let mut counter = ArrangementCounter::new(&r);
+        let draws = 20000;
+        let mut seen = HashMap::<String, usize>::new();
+        for _ in 0..draws {
+            let a = counter.sample(&mut rng).unwrap();
+            assert!(arrangements.contains(&a), "{} for {}", a, r);
+            *seen.entry(a).or_default() += 1;
+        }
+        assert_eq!(seen.len(), arrangements.len());
+        // about 2000 each, a standard deviation is about 42
+        let expected = draws / arrangements.len();
+        for (a, n) in seen {
+            assert!(n.abs_diff(expected) < expected / 10, "{} drawn {} times out of {}", a, n, draws);
+        }
+    }
 }
@@ -1,163 +1,347 @@
-use std::{collections::HashMap, iter};
-
-fn full_tilt_north(grid: &mut Vec<Vec<char>>) {
-    let mut northernmost_taken_space: Vec<i32> = iter::repeat(-1).take(grid[0].len()).collect();
-
-    // this feels more like univ C than idiomatic Rust
-    for y in 0..grid.len() {
-        for x in 0..grid[y].len() {
-            let c = grid[y][x];
-            if c == '#' {
-                northernmost_taken_space[x] = y as i32;
-            } else if c == 'O' {
-                if northernmost_taken_space[x] < (y as i32) - 1 {
-                    grid[(northernmost_taken_space[x] + 1) as usize][x] = 'O';
-                    grid[y][x] = '.';
-                    northernmost_taken_space[x] = northernmost_taken_space[x] + 1;
-                } else {
-                    northernmost_taken_space[x] = y as i32;
-                }
-            }
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::animation::{self, Frame};
+use crate::cycles;
+use crate::render::Renderer;
+
+/// one bit per cell, rows packed into u64 words
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BitGrid {
+    width: usize,
+    height: usize,
+    words_per_row: usize,
+    bits: Vec<u64>,
+}
+
+impl BitGrid {
+    fn new(width: usize, height: usize) -> BitGrid {
+        let words_per_row = (width + 63) / 64;
+        BitGrid {
+            width,
+            height,
+            words_per_row,
+            bits: vec![0; words_per_row * height],
         }
     }
-}
 
-fn full_tilt_south(grid: &mut Vec<Vec<char>>) {
-    let mut southernmost_taken_space: Vec<i32> = iter::repeat(grid.len() as i32)
-        .take(grid[0].len())
-        .collect();
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.bits[y * self.words_per_row + x / 64] & (1 << (x % 64)) != 0
+    }
 
-    for y in (0..grid.len()).rev() {
-        for x in 0..grid[y].len() {
-            let c = grid[y][x];
-            if c == '#' {
-                southernmost_taken_space[x] = y as i32;
-            } else if c == 'O' {
-                if southernmost_taken_space[x] > (y as i32) + 1 {
-                    grid[(southernmost_taken_space[x] - 1) as usize][x] = 'O';
-                    grid[y][x] = '.';
-                    southernmost_taken_space[x] = southernmost_taken_space[x] - 1;
-                } else {
-                    southernmost_taken_space[x] = y as i32;
+    fn set(&mut self, x: usize, y: usize) {
+        self.bits[y * self.words_per_row + x / 64] |= 1 << (x % 64);
+    }
+
+    fn row(&self, y: usize) -> &[u64] {
+        &self.bits[y * self.words_per_row..(y + 1) * self.words_per_row]
+    }
+
+    /// the west edge becomes the north edge
+    fn rotate_clockwise(&self) -> BitGrid {
+        // flipping the rows upside down then transposing is a clockwise rotation,
+        // and transposing goes 64x64 bits at a time
+        let mut rotated = BitGrid::new(self.height, self.width);
+        let mut block = [0u64; 64];
+        for by in 0..rotated.words_per_row {
+            for bx in 0..self.words_per_row {
+                for (i, word) in block.iter_mut().enumerate() {
+                    let y = by * 64 + i;
+                    *word = if y < self.height {
+                        self.bits[(self.height - 1 - y) * self.words_per_row + bx]
+                    } else {
+                        0
+                    };
+                }
+                transpose_block(&mut block);
+                for (j, word) in block.iter().enumerate() {
+                    let y = bx * 64 + j;
+                    if y < rotated.height {
+                        rotated.bits[y * rotated.words_per_row + by] = *word;
+                    }
                 }
             }
         }
+        return rotated;
     }
 }
 
-fn full_tilt_west(grid: &mut Vec<Vec<char>>) {
-    let mut westernmost_taken_space: Vec<i32> = iter::repeat(-1 as i32).take(grid.len()).collect();
+/// transpose a 64x64 bit matrix in place, bit c of word r going to bit r of word c
+fn transpose_block(block: &mut [u64; 64]) {
+    // swap the off-diagonal quadrants, then recurse into halves of halves (Hacker's Delight 7-3)
+    let mut j = 32;
+    let mut mask: u64 = 0x0000_0000_FFFF_FFFF;
+    while j != 0 {
+        let mut k = 0;
+        while k < 64 {
+            let t = ((block[k] >> j) ^ block[k + j]) & mask;
+            block[k] ^= t << j;
+            block[k + j] ^= t;
+            k = (k + j + 1) & !j;
+        }
+        j >>= 1;
+        mask ^= mask << j;
+    }
+}
 
-    // not an ideal access pattern but whatever
-    for x in 0..grid[0].len() {
-        for y in 0..grid.len() {
-            let c = grid[y][x];
-            if c == '#' {
-                westernmost_taken_space[y] = x as i32;
-            } else if c == 'O' {
-                if westernmost_taken_space[y] < (x as i32) - 1 {
-                    grid[y][(westernmost_taken_space[y] + 1) as usize] = 'O';
-                    grid[y][x] = '.';
-                    westernmost_taken_space[y] = westernmost_taken_space[y] + 1;
-                } else {
-                    westernmost_taken_space[y] = x as i32;
-                }
-            }
+/// rounded rocks move, cube rocks ("walls") don't
+#[derive(Debug, Clone)]
+struct Platform {
+    rocks: BitGrid,
+    /// walls in every orientation, indexed by the number of clockwise rotations
+    walls: Rc<[BitGrid; 4]>,
+    rotation: usize,
+}
 
-            //println!("(x={} y={})\n{:?}\n{}\n", x, y, westernmost_taken_space, _represent_grid(grid.to_vec()));
-        }
+// walls never move, so the rocks are the whole state
+impl PartialEq for Platform {
+    fn eq(&self, other: &Self) -> bool {
+        self.rotation == other.rotation && self.rocks == other.rocks
     }
 }
 
-fn full_tilt_east(grid: &mut Vec<Vec<char>>) {
-    let mut easternmost_taken_space: Vec<i32> = iter::repeat(grid[0].len() as i32)
-        .take(grid.len())
-        .collect();
+impl Eq for Platform {}
 
-    for x in (0..grid[0].len()).rev() {
-        for y in 0..grid.len() {
-            let c = grid[y][x];
-            if c == '#' {
-                easternmost_taken_space[y] = x as i32;
-            } else if c == 'O' {
-                if easternmost_taken_space[y] > (x as i32) + 1 {
-                    grid[y][(easternmost_taken_space[y] - 1) as usize] = 'O';
-                    grid[y][x] = '.';
-                    easternmost_taken_space[y] = easternmost_taken_space[y] - 1;
-                } else {
-                    easternmost_taken_space[y] = x as i32;
-                }
+impl Hash for Platform {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rotation.hash(state);
+        self.rocks.hash(state);
+    }
+}
+
+impl Platform {
+    fn width(&self) -> usize {
+        self.rocks.width
+    }
+
+    fn height(&self) -> usize {
+        self.rocks.height
+    }
+
+    fn rotate_clockwise(&mut self) {
+        self.rocks = self.rocks.rotate_clockwise();
+        self.rotation = (self.rotation + 1) % 4;
+    }
+
+    fn get(&self, x: usize, y: usize) -> char {
+        if self.walls[self.rotation].get(x, y) {
+            '#'
+        } else if self.rocks.get(x, y) {
+            'O'
+        } else {
+            '.'
+        }
+    }
+}
+
+/// roll every rock north; tilting in any other direction is done by rotating first
+fn tilt_north(grid: &mut Platform) {
+    let walls = &grid.walls[grid.rotation];
+    let rocks = &mut grid.rocks;
+    let wpr = rocks.words_per_row;
+    // rows above y have already settled, so a rock falls until it meets anything
+    for y in 1..rocks.height {
+        for w in 0..wpr {
+            let mut moving = rocks.bits[y * wpr + w];
+            let mut row = y;
+            while row > 0 && moving != 0 {
+                let above = (row - 1) * wpr + w;
+                moving &= !(rocks.bits[above] | walls.bits[above]);
+                rocks.bits[row * wpr + w] &= !moving;
+                rocks.bits[above] |= moving;
+                row -= 1;
             }
         }
     }
 }
 
-fn cycle_grid(grid: &mut Vec<Vec<char>>) {
-    full_tilt_north(grid);
-    full_tilt_west(grid);
-    full_tilt_south(grid);
-    full_tilt_east(grid);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    North,
+    West,
+    South,
+    East,
 }
 
-fn compute_north_load(grid: &Vec<Vec<char>>) -> u64 {
-    grid.iter()
-        .rev()
-        .enumerate()
-        .map(|(i, line)| ((i + 1) as u64) * line.iter().filter(|c| **c == 'O').count() as u64)
+impl Direction {
+    /// clockwise rotations that bring this edge to the north
+    fn rotation(&self) -> usize {
+        match self {
+            Direction::North => 0,
+            Direction::West => 1,
+            Direction::South => 2,
+            Direction::East => 3,
+        }
+    }
+}
+
+/// "NWSE" is the puzzle's spin cycle
+fn parse_tilts(input: &str) -> Result<Vec<Direction>, String> {
+    input
+        .chars()
+        .map(|c| match c.to_ascii_uppercase() {
+            'N' => Ok(Direction::North),
+            'W' => Ok(Direction::West),
+            'S' => Ok(Direction::South),
+            'E' => Ok(Direction::East),
+            _ => Err(format!("unknown tilt direction '{}', expected N, W, S or E", c)),
+        })
+        .collect()
+}
+
+fn tilt(grid: &mut Platform, direction: Direction) {
+    while grid.rotation != direction.rotation() {
+        grid.rotate_clockwise();
+    }
+    tilt_north(grid);
+}
+
+/// rotate back to the way the grid was parsed
+fn straighten(grid: &mut Platform) {
+    while grid.rotation != 0 {
+        grid.rotate_clockwise();
+    }
+}
+
+/// tilt in every direction of <sequence>, leaving the grid the right way up
+fn cycle_grid(grid: &mut Platform, sequence: &[Direction]) {
+    for direction in sequence {
+        tilt(grid, *direction);
+    }
+    straighten(grid);
+}
+
+fn compute_north_load(grid: &Platform) -> u64 {
+    (0..grid.height())
+        .map(|y| {
+            let count: u32 = grid.rocks.row(y).iter().map(|w| w.count_ones()).sum();
+            ((grid.height() - y) as u64) * count as u64
+        })
         .sum()
 }
 
-fn parse(input: String) -> Vec<Vec<char>> {
-    return input
-        .trim()
-        .split("\n")
-        .map(|line| line.chars().collect())
-        .collect();
+fn parse(input: String) -> Platform {
+    let lines: Vec<&str> = input.trim().split("\n").collect();
+    let mut rocks = BitGrid::new(lines[0].len(), lines.len());
+    let mut walls = BitGrid::new(lines[0].len(), lines.len());
+    for (y, line) in lines.iter().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                'O' => rocks.set(x, y),
+                '#' => walls.set(x, y),
+                _ => (),
+            }
+        }
+    }
+    let east = walls.rotate_clockwise();
+    let south = east.rotate_clockwise();
+    let west = south.rotate_clockwise();
+    return Platform {
+        rocks,
+        walls: Rc::new([walls, east, south, west]),
+        rotation: 0,
+    };
 }
 
-fn represent_grid(grid: &Vec<Vec<char>>) -> String {
-    return grid
-        .into_iter()
-        .map(|line| line.iter().collect())
-        .collect::<Vec<String>>()
-        .join("\n");
+fn to_frame(grid: &Platform) -> Frame {
+    let mut grid = grid.clone();
+    straighten(&mut grid);
+    return (0..grid.height())
+        .map(|y| (0..grid.width()).map(|x| grid.get(x, y)).collect())
+        .collect();
 }
 
 pub fn p1(input: String) {
     let grid = &mut parse(input);
-    full_tilt_north(grid);
+    let before = to_frame(grid);
+    tilt_north(grid);
+    let args = crate::args();
+    if let Some(path) = &args.animate {
+        animation::export(&[before.clone(), to_frame(grid)], path);
+    }
+    if args.render {
+        Renderer::from_args().show_all(&[before, to_frame(grid)]);
+    }
     println!("{}", compute_north_load(grid));
 }
 
 pub fn p2(input: String) {
-    let mut grid = parse(input);
-
-    let target_cycles = 1_000_000_000;
+    let args = crate::args();
+    let grid = parse(input);
 
-    let mut states_map = HashMap::<String, u32>::new();
-    let mut states_list = Vec::<Vec<Vec<char>>>::new();
+    let sequence = parse_tilts(&args.tilts).unwrap_or_else(|e| panic!("{}", e));
+    let step = |g: &Platform| {
+        let mut next = g.clone();
+        cycle_grid(&mut next, &sequence);
+        next
+    };
 
-    states_map.insert(represent_grid(&grid), 0);
-    states_list.push(grid.clone());
+    let Some((final_grid, cycle)) =
+        cycles::state_at(grid.clone(), step, args.cycles, args.cycle_mode, args.search_limit)
+    else {
+        eprintln!("no cycle found within {} cycles", args.search_limit.unwrap());
+        // no answer, and scripts must be able to tell
+        std::process::exit(1);
+    };
 
-    for i in 1..(500 as u32) {
-        cycle_grid(&mut grid);
-        //print!("{:>5.2}%\r", i as f32 /target_cycles as f32 * 100 as f32)
+    // only built when asked for, so that a bad --palette does not break plain runs
+    let renderer = args.render.then(Renderer::from_args);
+    let live = renderer.as_ref().is_some_and(|r| r.is_live());
+    let want_frames = args.animate.is_some() || live;
+    if args.every_step || want_frames {
+        // every state after that is a repeat
+        let last_new_step = cycle.map_or(args.cycles, |c| (c.start + c.length).min(args.cycles));
+        let mut g = grid;
+        let mut frames = Vec::from([to_frame(&g)]);
+        for i in 1..=last_new_step {
+            for direction in &sequence {
+                tilt(&mut g, *direction);
+                if want_frames {
+                    frames.push(to_frame(&g));
+                }
+            }
+            straighten(&mut g);
+            if args.every_step {
+                println!("after {} cycles: {}", i, compute_north_load(&g));
+            }
+        }
+        if let Some(path) = &args.animate {
+            animation::export(&frames, path);
+        }
+        if let Some(renderer) = renderer.as_ref().filter(|r| r.is_live()) {
+            renderer.show_all(&frames);
+        }
+    }
+    if let Some(renderer) = renderer.as_ref().filter(|r| !r.is_live()) {
+        renderer.show(&to_frame(&final_grid));
+    }
+    if let Some(c) = cycle {
+        println!("cycle of length {} starting after {} cycles", c.length, c.start);
+    }
+    println!("{}", compute_north_load(&final_grid));
+}
 
-        let repr = represent_grid(&grid);
-        if let Some(early_iter) = states_map.get(&repr) {
-            // f(n) = f(init_size+((n-init_size)%cycle_size)
-            let equivalent_target =
-                (*early_iter + ((target_cycles - *early_iter) % (i - *early_iter))) as usize;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            println!(
-                "{}",
-                compute_north_load(states_list.get(equivalent_target).unwrap())
-            );
-            break;
-        } else {
-            states_map.insert(repr, i);
-            states_list.push(grid.clone());
+    #[test]
+    fn rotation_matches_cell_by_cell() {
+        let (width, height) = (70, 130);
+        let mut grid = BitGrid::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                if (x * 7 + y * 13) % 5 == 0 {
+                    grid.set(x, y);
+                }
+            }
+        }
+        let rotated = grid.rotate_clockwise();
+        assert_eq!((rotated.width, rotated.height), (height, width));
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(grid.get(x, y), rotated.get(height - 1 - y, x), "({}, {})", x, y);
+            }
         }
     }
 }
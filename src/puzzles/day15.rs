@@ -1,5 +1,10 @@
-use regex::Regex;
-use std::{collections::VecDeque, iter};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::{Display, Formatter},
+    iter,
+};
+
+use crate::render::{text_frame, Renderer};
 
 fn calculate_hash(input: &str) -> u32 {
     let mut val = 0;
@@ -16,47 +21,144 @@ pub fn p1(input: String) {
     );
 }
 
-struct Op {
-    label: String,
-    op: char,
-    length: Option<u8>,
+/// the puzzle's HASHMAP: 256 boxes picked by calculate_hash, each keeping lenses in insertion order
+#[derive(Debug, Clone)]
+pub struct LensMap<V> {
+    boxes: Vec<VecDeque<(String, V)>>,
 }
 
-fn parse_ops(input: String) -> Vec<Op> {
-    let re = Regex::new(r"(?<label>[a-z]+)(?<op>[=-])(?<length>\d?)").unwrap();
-
-    input
-        .trim()
-        .split(",")
-        .map(|s| {
-            let c = re.captures(s).unwrap();
-            if &c["op"] == "-" {
-                return Op {
-                    label: c["label"].to_string(),
-                    op: '-',
-                    length: None,
-                };
-            } else {
-                return Op {
-                    label: c["label"].to_string(),
-                    op: '=',
-                    length: Some(c["length"].parse::<u8>().unwrap()),
-                };
-            }
-        })
-        .collect()
+impl<V> LensMap<V> {
+    pub const BOX_COUNT: usize = 256;
+
+    pub fn new() -> LensMap<V> {
+        LensMap {
+            boxes: iter::repeat_with(VecDeque::new).take(Self::BOX_COUNT).collect(),
+        }
+    }
+
+    pub fn box_of(label: &str) -> usize {
+        calculate_hash(label) as usize
+    }
+
+    /// replaces the value in place if the label is already there, goes to the back of its box otherwise
+    pub fn insert(&mut self, label: &str, value: V) {
+        let target_box = &mut self.boxes[Self::box_of(label)];
+        match target_box.iter_mut().find(|(l, _)| l == label) {
+            Some((_, existing)) => *existing = value,
+            None => target_box.push_back((label.to_string(), value)),
+        }
+    }
+
+    pub fn remove(&mut self, label: &str) -> Option<V> {
+        let target_box = &mut self.boxes[Self::box_of(label)];
+        let i = target_box.iter().position(|(l, _)| l == label)?;
+        return target_box.remove(i).map(|(_, value)| value);
+    }
+
+    pub fn get(&self, label: &str) -> Option<&V> {
+        self.boxes[Self::box_of(label)]
+            .iter()
+            .find(|(l, _)| l == label)
+            .map(|(_, value)| value)
+    }
+
+    /// lenses in one box, front first
+    pub fn lenses(&self, box_i: usize) -> impl Iterator<Item = (&str, &V)> {
+        self.boxes[box_i].iter().map(|(label, value)| (label.as_str(), value))
+    }
+
+    /// (box, label, value) by box, then front to back
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str, &V)> {
+        (0..Self::BOX_COUNT).flat_map(move |box_i| self.lenses(box_i).map(move |(label, value)| (box_i, label, value)))
+    }
+}
+
+impl<V: Copy + Into<u64>> LensMap<V> {
+    pub fn focusing_power(&self) -> u64 {
+        let mut slot = 0;
+        let mut last_box = usize::MAX;
+        self.iter()
+            .map(|(box_i, _label, length)| {
+                slot = if box_i == last_box { slot + 1 } else { 1 };
+                last_box = box_i;
+                (box_i as u64 + 1) * slot * (*length).into()
+            })
+            .sum()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Op {
+    Remove { label: String },
+    Insert { label: String, length: u32 },
+}
+
+impl Op {
+    fn label(&self) -> &str {
+        match self {
+            Op::Remove { label } | Op::Insert { label, .. } => label,
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Op::Remove { label } => write!(f, "{}-", label),
+            Op::Insert { label, length } => write!(f, "{}={}", label, length),
+        }
+    }
 }
 
-fn _represent_boxes(boxes: &Vec<VecDeque<(String, u8)>>) -> String {
-    boxes
-        .into_iter()
-        .enumerate()
-        .filter(|(_i, b)| b.len() > 0)
-        .map(|(i, b)| {
+/// "ab-" or "ab=12"
+fn parse_op(step: &str) -> Result<Op, String> {
+    let label_length = step.find(|c: char| !c.is_ascii_lowercase()).unwrap_or(step.len());
+    let (label, rest) = step.split_at(label_length);
+    if label.is_empty() {
+        return Err("missing label".to_string());
+    }
+    let label = label.to_string();
+    if rest == "-" {
+        return Ok(Op::Remove { label });
+    }
+    let Some(length) = rest.strip_prefix("=") else {
+        return Err(format!("expected - or = after '{}'", label));
+    };
+    if length.is_empty() || !length.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("bad focal length '{}'", length));
+    }
+    match length.parse::<u32>() {
+        Ok(length) => Ok(Op::Insert { label, length }),
+        Err(e) => Err(format!("bad focal length '{}': {}", length, e)),
+    }
+}
+
+/// every bad step is reported, not just the first one
+fn parse_ops(input: String) -> Result<Vec<Op>, Vec<String>> {
+    let mut ops = Vec::new();
+    let mut errors = Vec::new();
+    for (i, step) in input.trim().split(",").enumerate() {
+        match parse_op(step.trim()) {
+            Ok(op) => ops.push(op),
+            Err(e) => errors.push(format!("step {} '{}': {}", i + 1, step, e)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(ops)
+    } else {
+        Err(errors)
+    }
+}
+
+fn represent_boxes(boxes: &LensMap<u32>) -> String {
+    (0..LensMap::<u32>::BOX_COUNT)
+        .filter(|i| boxes.lenses(*i).next().is_some())
+        .map(|i| {
             format!(
                 "Box {:>3}: {}\n",
                 i,
-                b.into_iter()
+                boxes
+                    .lenses(i)
                     .map(|(label, length)| format!("[{} {}] ", label, length))
                     .collect::<String>()
             )
@@ -64,45 +166,105 @@ fn _represent_boxes(boxes: &Vec<VecDeque<(String, u8)>>) -> String {
         .collect::<String>()
 }
 
-fn compute_focusing_power(boxes: &Vec<VecDeque<(String, u8)>>) -> u32 {
-    boxes
-        .iter()
-        .enumerate()
-        .filter(|(_b_i, b)| b.len() > 0)
-        .map(|(b_i, b)| {
-            b.iter()
-                .enumerate()
-                .map(|(l_i, (_label, length))| (b_i + 1) * (l_i + 1) * *length as usize)
-                .sum::<usize>()
-        })
-        .sum::<usize>() as u32
+/// whether --trace, --trace-label or --trace-box want to see the boxes after <op>
+fn is_traced(op: &Op) -> bool {
+    let args = crate::args();
+    if args.trace_label.is_none() && args.trace_box.is_none() {
+        return args.trace;
+    }
+    return args.trace_label.as_deref() == Some(op.label())
+        || args.trace_box == Some(LensMap::<u32>::box_of(op.label()));
+}
+
+/// boxes that more than one label went into over the whole sequence
+fn print_collisions(ops: &[Op]) {
+    let mut labels_per_box = BTreeMap::<usize, BTreeSet<&str>>::new();
+    for op in ops {
+        labels_per_box.entry(LensMap::<u32>::box_of(op.label())).or_default().insert(op.label());
+    }
+    println!("Collisions:");
+    for (box_i, labels) in labels_per_box.iter().filter(|(_, labels)| labels.len() > 1) {
+        println!("Box {:>3}: {}", box_i, labels.iter().copied().collect::<Vec<&str>>().join(", "));
+    }
+    println!();
 }
 
 pub fn p2(input: String) {
-    let ops = parse_ops(input);
+    let ops = match parse_ops(input) {
+        Ok(ops) => ops,
+        Err(errors) => {
+            errors.iter().for_each(|e| eprintln!("{}", e));
+            // no answer, and scripts must be able to tell
+            std::process::exit(1);
+        }
+    };
 
-    let mut boxes = iter::repeat(VecDeque::<(String, u8)>::new())
-        .take(256)
-        .collect::<Vec<VecDeque<(String, u8)>>>();
+    let mut boxes = LensMap::<u32>::new();
 
-    for op in ops {
-        let target_box = &mut boxes[calculate_hash(&op.label) as usize];
-        let existing_i = target_box
-            .iter()
-            .position(|(label, _length)| *label == op.label);
-        if op.op == '=' {
-            if existing_i.is_some() {
-                target_box[existing_i.unwrap()].1 = op.length.unwrap();
-            } else {
-                target_box.push_back((op.label, op.length.unwrap()))
+    let args = crate::args();
+    let renderer = args.render.then(Renderer::from_args);
+    let tracing = args.trace || args.trace_label.is_some() || args.trace_box.is_some();
+
+    for op in ops.iter() {
+        match op {
+            Op::Insert { label, length } => boxes.insert(label, *length),
+            Op::Remove { label } => {
+                boxes.remove(label);
             }
-        } else {
-            if existing_i.is_some() {
-                target_box.remove(existing_i.unwrap());
+        }
+        if tracing && is_traced(op) {
+            println!("After \"{}\":", op);
+            if args.trace_label.as_deref() == Some(op.label()) {
+                match boxes.get(op.label()) {
+                    Some(length) => println!("{} is {} in box {}", op.label(), length, LensMap::<u32>::box_of(op.label())),
+                    None => println!("{} is in no box", op.label()),
+                }
             }
+            println!("{}", represent_boxes(&boxes));
         }
+        if let Some(renderer) = renderer.as_ref().filter(|r| r.is_live()) {
+            renderer.show(&text_frame(&represent_boxes(&boxes)));
+        }
+    }
+
+    if let Some(renderer) = renderer.as_ref().filter(|r| !r.is_live()) {
+        renderer.show(&text_frame(&represent_boxes(&boxes)));
+    }
+    if tracing {
+        print_collisions(&ops);
+    }
+    println!("{}", boxes.focusing_power());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_steps_are_reported() {
+        let errors = parse_ops("rn=1,=2,cm-,qp=,ab=x,pc*,ot=12".to_string()).unwrap_err();
+        assert_eq!(errors.len(), 4, "{:?}", errors);
+        assert!(errors[0].starts_with("step 2 "));
+        assert_eq!(
+            parse_ops("ot=12,cm-".to_string()).unwrap(),
+            Vec::from([
+                Op::Insert { label: "ot".to_string(), length: 12 },
+                Op::Remove { label: "cm".to_string() }
+            ])
+        );
     }
 
-    //println!("{}", _represent_boxes(&boxes));
-    println!("{}", compute_focusing_power(&boxes));
+    #[test]
+    fn lens_map_keeps_insertion_order() {
+        let mut boxes = LensMap::<u32>::new();
+        for (label, length) in [("rn", 1), ("cm", 2), ("qp", 3), ("rn", 4)] {
+            boxes.insert(label, length);
+        }
+        assert_eq!(boxes.remove("qp"), Some(3));
+        assert_eq!(boxes.remove("qp"), None);
+        assert_eq!(boxes.get("rn"), Some(&4));
+        let order: Vec<(usize, &str, &u32)> = boxes.iter().collect();
+        assert_eq!(order, Vec::from([(0, "rn", &4), (0, "cm", &2)]));
+        assert_eq!(boxes.focusing_power(), 4 + 2 * 2);
+    }
 }
@@ -1,10 +1,17 @@
 use std::{
     cmp::{max, min},
-    iter,
+    fmt::Write,
+    fs, iter,
+    path::Path,
 };
 
 use regex::Regex;
 
+use crate::animation::{self, Frame};
+use crate::flood::{self, Connectivity};
+use crate::polygon::{Containment, Point, Polygon};
+use crate::render::Renderer;
+
 /*
 after much thought given to line rasterization, let's go with the naivest solution
 because all lines are parallel to an axis
@@ -12,28 +19,40 @@ because all lines are parallel to an axis
 
 #[derive(Debug)]
 struct Edge {
-    points: Vec<Point>,
     color: String,
-    _origin: Point,
-    _dir: char,
-    _length: u32,
+    origin: Point,
+    dir: char,
+    length: u32,
 }
 
 impl Edge {
+    /// first and last point dug by this edge (the origin was dug by the previous one),
+    /// top left to bottom right
+    fn bounds(&self) -> (Point, Point) {
+        let (o, l) = (&self.origin, self.length as i32);
+        match self.dir {
+            'U' => (Point { x: o.x, y: o.y - l }, Point { x: o.x, y: o.y - 1 }),
+            'L' => (Point { x: o.x - l, y: o.y }, Point { x: o.x - 1, y: o.y }),
+            'D' => (Point { x: o.x, y: o.y + 1 }, Point { x: o.x, y: o.y + l }),
+            'R' => (Point { x: o.x + 1, y: o.y }, Point { x: o.x + l, y: o.y }),
+            _ => panic!(),
+        }
+    }
+
+    /// every point dug by this edge, only sensible at part 1 scale
+    fn points(&self) -> Vec<Point> {
+        let (first, last) = self.bounds();
+        (first.y..=last.y)
+            .flat_map(|y| (first.x..=last.x).map(move |x| Point { x, y }))
+            .collect()
+    }
+
     fn has_point(&self, p: &Point) -> bool {
-        // this works because we built our points vector from top left to bottom right
-        return self.points.first().unwrap().x <= p.x
-            && self.points.first().unwrap().y <= p.y
-            && self.points.last().unwrap().x >= p.x
-            && self.points.last().unwrap().y >= p.y;
+        let (first, last) = self.bounds();
+        return first.x <= p.x && first.y <= p.y && last.x >= p.x && last.y >= p.y;
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-struct Point {
-    x: i32,
-    y: i32,
-}
 
 /// returns (list of ops, up left coords, down right coords)
 fn parse_input(input: String) -> (Vec<Edge>, Point, Point) {
@@ -47,40 +66,11 @@ fn parse_input(input: String) -> (Vec<Edge>, Point, Point) {
         let c = re.captures(line).unwrap();
         let length = c["length"].parse::<i32>().unwrap();
 
-        let points: Vec<Point> = match &c["direction"] {
-            "U" => ((current_coords.y - length)..(current_coords.y))
-                .map(|y| Point {
-                    x: current_coords.x,
-                    y: y,
-                })
-                .collect(),
-            "L" => ((current_coords.x - length)..(current_coords.x))
-                .map(|x| Point {
-                    x: x,
-                    y: current_coords.y,
-                })
-                .collect(),
-            "D" => ((current_coords.y + 1)..(current_coords.y + length + 1))
-                .map(|y| Point {
-                    x: current_coords.x,
-                    y: y,
-                })
-                .collect(),
-            "R" => ((current_coords.x + 1)..(current_coords.x + length + 1))
-                .map(|x| Point {
-                    x: x,
-                    y: current_coords.y,
-                })
-                .collect(),
-            _ => panic!(),
-        };
-
         edges.push(Edge {
-            points: points,
             color: c["color"].to_string(),
-            _origin: current_coords.clone(),
-            _dir: c["direction"].chars().next().unwrap(),
-            _length: length as u32,
+            origin: current_coords.clone(),
+            dir: c["direction"].chars().next().unwrap(),
+            length: length as u32,
         });
 
         match &c["direction"] {
@@ -98,7 +88,7 @@ fn parse_input(input: String) -> (Vec<Edge>, Point, Point) {
 
     // for edge in &edges {
     //     println!("{:?}", edge);
-    //     for point in &edge.points {
+    //     for point in &edge.points() {
     //         println!(" => {:?}", *point);
     //     }
     // }
@@ -127,42 +117,9 @@ fn parse_input_p2(input: String) -> (Vec<Edge>, Point, Point) {
     return parse_input(new_input);
 }
 
-fn _represent_grid(grid: Vec<Vec<Option<String>>>) -> String {
-    grid.iter()
-        .map(|line| {
-            line.iter()
-                .map(|point| {
-                    if point.is_none() {
-                        '.'
-                    } else {
-                        if point.as_ref().unwrap() == "ffffff" {
-                            '$'
-                        } else {
-                            '#'
-                        }
-                    }
-                })
-                .collect::<String>()
-                + "\n"
-        })
-        .collect()
-}
-
-
-fn _represent_grid_p2(grid: Vec<Vec<Option<char>>>) -> String {
+fn to_frame_p2(grid: &[Vec<Option<char>>]) -> Frame {
     grid.iter()
-        .map(|line| {
-            line.iter()
-                .map(|point| {
-                    if point.is_none() {
-                        '.'
-                    } else {
-                        *point.as_ref().unwrap()
-                    }
-                })
-                .collect::<String>()
-                + "\n"
-        })
+        .map(|line| line.iter().map(|point| point.unwrap_or('.')).collect())
         .collect()
 }
 
@@ -197,7 +154,7 @@ fn build_grid(
             for edge in edges.iter() {
                 if edge.has_point(&geo_coords) {
                     grid[y][x] = Some( //Some(edge.color.clone());
-                        match edge._dir {
+                        match edge.dir {
                             'U' => "▲".to_string(),
                             'D' => "▼".to_string(),
                             'L' => "◄".to_string(),
@@ -212,58 +169,188 @@ fn build_grid(
     return grid;
 }
 
-fn _get_point_inside_polygon(grid: &Vec<Vec<Option<String>>>) -> Option<Point> {
-    // heuristic for getting a point that is inside the grid
-    let mut wall_encountered = false;
-    let y = grid.len()/2;
-    for (x, val) in grid[y].iter().enumerate() {
-        if val.is_some() {
-            wall_encountered = true;
-        }
-        if val.is_none() && wall_encountered {
-            return Some(Point{x: x as i32, y: y as i32}); 
+/// first empty cell (in grid coordinates) that is inside the trench
+fn get_point_inside_polygon(grid: &Vec<Vec<Option<String>>>, polygon: &Polygon, farthest_ul: &Point) -> Option<Point> {
+    for (y, line) in grid.iter().enumerate() {
+        for (x, val) in line.iter().enumerate() {
+            let geo_coords = Point { x: x as i32 + farthest_ul.x, y: y as i32 + farthest_ul.y };
+            if val.is_none() && polygon.contains(&geo_coords) == Containment::Inside {
+                return Some(Point { x: x as i32, y: y as i32 });
+            }
         }
     }
     return None;
 }
 
-fn _flood_point(grid: &mut Vec<Vec<Option<String>>>, p: Point) {
-    let y = p.y as usize;
-    let x = p.x as usize;
-    grid[y][x] = Some("ffffff".to_string());
-    if y - 1 > 0 && grid[y-1][x].is_none() {
-        _flood_point(grid, Point{y: p.y-1, x: p.x});
+fn flood_fill_grid(
+    grid: &mut Vec<Vec<Option<String>>>,
+    polygon: &Polygon,
+    farthest_ul: &Point,
+    mode: flood::Mode,
+    connectivity: Connectivity,
+) {
+    // a trench with nothing inside has nothing to flood
+    if let Some(p) = get_point_inside_polygon(grid, polygon, farthest_ul) {
+        flood::fill(grid, (p.x as usize, p.y as usize), mode, connectivity, |c| c.is_none(), Some("ffffff".to_string()));
     }
-    if y + 1 < grid.len() && grid[y+1][x].is_none() {
-        _flood_point(grid, Point{y: p.y+1, x: p.x});
+}
+
+/// the trench after each edge is dug
+fn dig_frames(edges: &[Edge], farthest_ul: &Point, farthest_dr: &Point) -> Vec<Frame> {
+    let width = (farthest_dr.x + 1 - farthest_ul.x) as usize;
+    let height = (farthest_dr.y + 1 - farthest_ul.y) as usize;
+    let mut frame: Frame = vec![vec!['.'; width]; height];
+
+    let mut frames = Vec::from([frame.clone()]);
+    for edge in edges {
+        for p in &edge.points() {
+            frame[(p.y - farthest_ul.y) as usize][(p.x - farthest_ul.x) as usize] = '#';
+        }
+        frames.push(frame.clone());
+    }
+    return frames;
+}
+
+/// trench as #, flooded interior as $
+fn to_frame(grid: &Vec<Vec<Option<String>>>) -> Frame {
+    grid.iter()
+        .map(|line| {
+            line.iter()
+                .map(|point| match point.as_deref() {
+                    None => '.',
+                    Some("ffffff") => '$',
+                    Some(_) => '#',
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn trench_polygon(edges: &[Edge]) -> Polygon {
+    Polygon::new(edges.iter().map(|e| e.origin).collect()).unwrap()
+}
+
+/// lagoon size from the corners alone: the trench's center line encloses interior
+/// points (counted with Pick's theorem) and goes through the boundary ones
+fn lagoon_area(edges: &[Edge]) -> u64 {
+    let polygon = trench_polygon(edges);
+    // interior + boundary, written so that it also holds for a trench with nothing inside
+    return polygon.area() + polygon.perimeter() / 2 + 1;
+}
+
+fn describe_trench(edges: &[Edge]) {
+    let polygon = trench_polygon(edges);
+    if polygon.is_self_intersecting() {
+        println!("the trench crosses itself, so the lagoon size is meaningless");
     }
-    if x - 1 > 0 && grid[y][x-1].is_none() {
-        _flood_point(grid, Point{y: p.y, x: p.x-1});
+    println!("{} corners, {:?}", polygon.vertices().len(), polygon.orientation());
+    let inside = match polygon.interior_lattice_points() {
+        Some(points) => points.to_string(),
+        None => "no".to_string(),
+    };
+    println!("perimeter {}, area {}, {} points inside", polygon.perimeter(), polygon.area(), inside);
+}
+
+/// the trench as vectors: one line per edge in its own colour, over the shaded lagoon,
+/// so that it stays small at part 2 scale
+fn to_svg(edges: &[Edge], labels: bool) -> String {
+    let polygon = trench_polygon(edges);
+    let (min_x, max_x) = polygon.vertices().iter().fold((i32::MAX, i32::MIN), |(lo, hi), p| (lo.min(p.x), hi.max(p.x)));
+    let (min_y, max_y) = polygon.vertices().iter().fold((i32::MAX, i32::MIN), |(lo, hi), p| (lo.min(p.y), hi.max(p.y)));
+    let span = max(max_x - min_x, max_y - min_y).max(1) as i64;
+    // the trench is one cell wide around the line through the cell centers
+    let margin = span / 20 + 1;
+    let font_size = (span / 60).max(1);
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        min_x as i64 - margin,
+        min_y as i64 - margin,
+        (max_x - min_x) as i64 + 2 * margin,
+        (max_y - min_y) as i64 + 2 * margin
+    )
+    .unwrap();
+    let points = polygon.vertices().iter().map(|p| format!("{},{}", p.x, p.y)).collect::<Vec<String>>().join(" ");
+    writeln!(svg, r##"<polygon points="{}" fill="#3060c0" fill-opacity="0.3" stroke="none"/>"##, points).unwrap();
+    for (edge, (a, b)) in edges.iter().zip(polygon.edges()) {
+        writeln!(
+            svg,
+            r##"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="#{}" stroke-width="3" stroke-linecap="square" vector-effect="non-scaling-stroke"/>"##,
+            a.x, a.y, b.x, b.y, edge.color
+        )
+        .unwrap();
     }
-    if x + 1 < grid[y].len() && grid[y][x+1].is_none() {
-        _flood_point(grid, Point{y: p.y, x: p.x+1});
+    if labels {
+        for (i, p) in polygon.vertices().iter().enumerate() {
+            writeln!(
+                svg,
+                r#"<text x="{}" y="{}" font-size="{}" font-family="monospace">{} ({},{})</text>"#,
+                p.x, p.y, font_size, i, p.x, p.y
+            )
+            .unwrap();
+        }
     }
+    svg.push_str("</svg>\n");
+    return svg;
 }
 
-fn flood_fill_grid(grid: &mut Vec<Vec<Option<String>>>) {
-    _flood_point(grid, _get_point_inside_polygon(grid).unwrap());
+fn export_svg(edges: &[Edge], path: &Path) {
+    fs::write(path, to_svg(edges, crate::args().svg_labels)).expect("Unable to write SVG");
 }
 
 pub fn p1(input: String) {
     let (edges, ful, fdr) = parse_input(input);
+    let args = crate::args();
+    if args.report {
+        describe_trench(&edges);
+    }
+    if let Some(path) = &args.svg {
+        export_svg(&edges, path);
+    }
+    let renderer = args.render.then(Renderer::from_args);
+    if !args.raster && args.animate.is_none() && !args.render {
+        println!("{}", lagoon_area(&edges));
+        return;
+    }
+
+    let live = renderer.as_ref().is_some_and(|r| r.is_live());
+    let mut frames = if args.animate.is_some() || live {
+        dig_frames(&edges, &ful, &fdr)
+    } else {
+        Vec::new()
+    };
+    let area = lagoon_area(&edges);
+    let polygon = trench_polygon(&edges);
     let mut grid = build_grid(edges, ful, fdr);
 
-    flood_fill_grid(&mut grid);
-    
-    //println!("{}", _represent_grid(grid));
-    println!("{}", compute_volume(grid));
+    flood_fill_grid(&mut grid, &polygon, &ful, args.flood_mode, args.flood_connectivity);
+    frames.push(to_frame(&grid));
+    if let Some(path) = &args.animate {
+        animation::export(&frames, path);
+    }
+    if let Some(renderer) = &renderer {
+        renderer.show_all(&frames);
+    }
+    let raster = compute_volume(grid) as u64;
+    if args.raster {
+        if area != raster {
+            println!("raster and shoelace disagree: {} vs {}", raster, area);
+        }
+        println!("{}", raster);
+    } else {
+        println!("{}", area);
+    }
 }
 
 
+/// with a <renderer>, the lines are also kept to be shown at the end (small inputs only)
 fn compute_polygon(
     edges: Vec<Edge>,
     farthest_ul: Point,
     farthest_dr: Point,
+    renderer: Option<&Renderer>,
 ) -> u64 {
     let offset = Point{x: -farthest_ul.x, y: -farthest_ul.y};
     let nb_lines = (farthest_dr.y + 1 + offset.y) as usize;
@@ -271,7 +358,7 @@ fn compute_polygon(
     
     let mut total_area = 0u64;
     
-    //let mut _grid: Vec<Vec<Option<char>>> = Vec::new();
+    let mut grid: Vec<Vec<Option<char>>> = Vec::new();
     
     let mut last_line : Vec<Option<char>> = vec![None; line_length];
     for y in 0..nb_lines {
@@ -282,7 +369,7 @@ fn compute_polygon(
             for edge in edges.iter() {
                 if edge.has_point(&geo_coords) {
                     current_line[x] = Some(
-                        match edge._dir {
+                        match edge.dir {
                             'U' => '▲',
                             'D' => '▼',
                             'L' => '◄',
@@ -306,13 +393,17 @@ fn compute_polygon(
                 }
             }
         }
-        //_grid.push(current_line.clone());
+        if renderer.is_some() {
+            grid.push(current_line.clone());
+        }
         last_line = current_line;
         if y%100 == 0 {
             println!("line {:>20}/{}", y, nb_lines);
         }
     }
-    //println!("{}", _represent_grid_p2(_grid));
+    if let Some(renderer) = renderer {
+        renderer.show(&to_frame_p2(&grid));
+    }
     
     return total_area;
 }
@@ -320,7 +411,41 @@ fn compute_polygon(
 
 pub fn p2(input: String) {
     let (edges, ful, fdr) = parse_input_p2(input);
-    let area = compute_polygon(edges, ful, fdr);
+    if crate::args().report {
+        describe_trench(&edges);
+    }
+    if let Some(path) = &crate::args().svg {
+        export_svg(&edges, path);
+    }
+    if !crate::args().raster {
+        println!("{}", lagoon_area(&edges));
+        return;
+    }
 
-    println!("{}", area);
+    let area = lagoon_area(&edges);
+    let renderer = crate::args().render.then(Renderer::from_args);
+    let raster = compute_polygon(edges, ful, fdr, renderer.as_ref());
+    if area != raster {
+        println!("raster and shoelace disagree: {} vs {}", raster, area);
+    }
+    println!("{}", raster);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shoelace_matches_raster() {
+        let input = std::fs::read_to_string("input/day18_ex").unwrap();
+        let (edges, ful, fdr) = parse_input(input.clone());
+        let area = lagoon_area(&edges);
+        let polygon = trench_polygon(&edges);
+        let mut grid = build_grid(edges, ful, fdr);
+        flood_fill_grid(&mut grid, &polygon, &ful, flood::Mode::Queue, Connectivity::Four);
+        assert_eq!(area, compute_volume(grid) as u64);
+
+        let (edges, _, _) = parse_input_p2(input);
+        assert_eq!(lagoon_area(&edges), 952408144115);
+    }
 }
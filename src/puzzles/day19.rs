@@ -1,25 +1,380 @@
-use std::hash::{Hash, Hasher};
-use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Instant;
 
-mod day19_workflows;
+use petgraph::algo::tarjan_scc;
+use petgraph::dot::Dot;
+use petgraph::graph::{Graph, NodeIndex};
+use petgraph::visit::Dfs;
+
+use crate::fingerprint::fingerprint;
+use crate::workflows::*;
+
+/// generated by build.rs from every input/day19* file
+mod day19_workflows {
+    include!(concat!(env!("OUT_DIR"), "/day19_workflows.rs"));
+}
 
 use day19_workflows::get_index;
 
+/// how many of the parts in <ratings> end up accepted when starting at <workflow>
+fn count_accepted(workflows: &Workflows, workflow: &str, ratings: RatingBox) -> u64 {
+    return count_accepted_on_path(workflows, &mut Vec::from([workflow]), ratings);
+}
+
+/// <path> is the workflows that led here, the current one last
+fn count_accepted_on_path<'a>(workflows: &'a Workflows, path: &mut Vec<&'a str>, ratings: RatingBox) -> u64 {
+    let workflow = *path.last().unwrap();
+    let mut rest = ratings;
+    let mut accepted = 0;
+    for branch in &workflows[workflow] {
+        if volume(&rest) == 0 {
+            break;
+        }
+        let matching = match &branch.condition {
+            Some(condition) => {
+                let (matching, not_matching) = split(&rest, condition);
+                rest = not_matching;
+                matching
+            }
+            None => std::mem::replace(&mut rest, [(0, 0); 4]),
+        };
+        if volume(&matching) == 0 {
+            continue;
+        }
+        accepted += match &branch.destination {
+            Destination::Accept => volume(&matching),
+            Destination::Reject => 0,
+            Destination::Workflow(id) => {
+                // these parts all came the same way, so they would keep going round
+                if path.contains(&id.as_str()) {
+                    panic!("workflows go round a cycle forever: {} -> {}", path.join(" -> "), id);
+                }
+                path.push(id);
+                let accepted = count_accepted_on_path(workflows, path, matching);
+                path.pop();
+                accepted
+            }
+        };
+    }
+    return accepted;
+}
+
+
+/// workflows, A and R as nodes, one edge per rule labelled with its condition
+fn build_workflow_graph(workflows: &Workflows) -> (Graph<String, String>, HashMap<String, NodeIndex>) {
+    let mut graph = Graph::<String, String>::new();
+    let mut nodes = HashMap::<String, NodeIndex>::new();
+    let mut ids: Vec<&String> = workflows.keys().collect();
+    ids.sort();
+    for id in ["A", "R"].iter().copied().chain(ids.iter().map(|id| id.as_str())) {
+        nodes.insert(id.to_string(), graph.add_node(id.to_string()));
+    }
+    for id in ids {
+        for branch in &workflows[id] {
+            let target = branch.destination.to_string();
+            // a destination that is not defined anywhere still gets a node, so that it shows up
+            let to = *nodes.entry(target.clone()).or_insert_with(|| graph.add_node(target));
+            let label = branch.condition.as_ref().map_or(String::new(), |c| c.to_string());
+            graph.add_edge(nodes[id], to, label);
+        }
+    }
+    return (graph, nodes);
+}
+
+fn workflow_graph_to_dot(graph: &Graph<String, String>) -> String {
+    let node_attributes = |_, (_, id): (NodeIndex, &String)| match id.as_str() {
+        "A" => "style = filled fillcolor = palegreen".to_string(),
+        "R" => "style = filled fillcolor = lightcoral".to_string(),
+        _ => String::new(),
+    };
+    return format!("{}", Dot::with_attr_getters(graph, &[], &|_, _| String::new(), &node_attributes));
+}
+
+#[derive(Debug, Default)]
+struct Findings {
+    unreachable: Vec<String>,
+    /// workflows that can lead back to themselves, one group per cycle
+    cycles: Vec<Vec<String>>,
+    /// (workflow, index of the rule) for rules that no part can get to
+    dead_branches: Vec<(String, usize)>,
+    /// workflows that accept (true) or reject (false) everything that reaches them
+    constant: BTreeMap<String, bool>,
+}
+
+fn analyze(workflows: &Workflows) -> Findings {
+    let (graph, nodes) = build_workflow_graph(workflows);
+    let mut findings = Findings::default();
+
+    let mut reached = HashSet::<NodeIndex>::new();
+    if let Some(start) = nodes.get("in") {
+        let mut dfs = Dfs::new(&graph, *start);
+        while let Some(node) = dfs.next(&graph) {
+            reached.insert(node);
+        }
+    }
+    findings.unreachable = workflows.keys().filter(|id| !reached.contains(&nodes[*id])).cloned().collect();
+    findings.unreachable.sort();
+
+    for component in tarjan_scc(&graph) {
+        if component.len() > 1 || graph.contains_edge(component[0], component[0]) {
+            let mut ids: Vec<String> = component.iter().map(|node| graph[*node].clone()).collect();
+            ids.sort();
+            findings.cycles.push(ids);
+        }
+    }
+    findings.cycles.sort();
+
+    let dead: HashMap<&String, HashSet<usize>> = workflows.iter().map(|(id, branches)| (id, dead_branches(branches))).collect();
+    for (id, branches) in dead.iter() {
+        findings.dead_branches.extend(branches.iter().map(|i| (id.to_string(), *i)));
+    }
+    findings.dead_branches.sort();
+
+    // a workflow is constant if every rule that can fire leads somewhere constant, A and R being the obvious ones
+    loop {
+        let mut changed = false;
+        for (id, branches) in workflows {
+            if findings.constant.contains_key(id) {
+                continue;
+            }
+            let outcomes: Option<HashSet<bool>> = branches
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !dead[id].contains(i))
+                .map(|(_, branch)| match &branch.destination {
+                    Destination::Accept => Some(true),
+                    Destination::Reject => Some(false),
+                    Destination::Workflow(next) => findings.constant.get(next).copied(),
+                })
+                .collect();
+            if let Some(outcomes) = outcomes {
+                if outcomes.len() == 1 {
+                    findings.constant.insert(id.clone(), outcomes.into_iter().next().unwrap());
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    return findings;
+}
+
+/// print what analyze finds and write the DOT graph, if asked for
+fn inspect_workflows(workflows: &Workflows) {
+    let args = crate::args();
+    if let Some(path) = &args.workflow_dot {
+        std::fs::write(path, workflow_graph_to_dot(&build_workflow_graph(workflows).0)).expect("Unable to write file");
+    }
+    if !args.report {
+        return;
+    }
+    let findings = analyze(workflows);
+    let list = |ids: Vec<&String>| if ids.is_empty() { "none".to_string() } else { ids.iter().map(|id| id.as_str()).collect::<Vec<&str>>().join(", ") };
+    println!("{} workflows", workflows.len());
+    println!("unreachable from in: {}", list(findings.unreachable.iter().collect()));
+    for cycle in &findings.cycles {
+        println!("cycle through {}", cycle.join(", "));
+    }
+    for (id, i) in &findings.dead_branches {
+        println!("rule {} of {} can never fire: {}", i + 1, id, workflows[id][*i]);
+    }
+    let constant = |accepts: bool| list(findings.constant.iter().filter(|(_, a)| **a == accepts).map(|(id, _)| id).collect());
+    println!("always A: {}", constant(true));
+    println!("always R: {}", constant(false));
+    let optimized = optimize(workflows);
+    let rules = |workflows: &Workflows| workflows.values().map(|branches| branches.len()).sum::<usize>();
+    println!(
+        "optimized from {} workflows and {} rules to {} and {}",
+        workflows.len(),
+        rules(workflows),
+        optimized.len(),
+        rules(&optimized)
+    );
+    println!();
+}
+
+/// whether <workflows> sends <part> to A, starting at "in"
+fn is_accepted(workflows: &Workflows, part: &[u32; 4]) -> bool {
+    let mut workflow = "in";
+    // going through more workflows than there are means going through one twice, with the same part: forever
+    for _ in 0..=workflows.len() {
+        let branch = workflows
+            .get(workflow)
+            .unwrap_or_else(|| panic!("no workflow named {}", workflow))
+            .iter()
+            .find(|branch| match &branch.condition {
+                Some(c) if c.less_than => part[c.category] < c.value,
+                Some(c) => part[c.category] > c.value,
+                None => true,
+            })
+            .expect("workflow without a fallback rule");
+        match &branch.destination {
+            Destination::Accept => return true,
+            Destination::Reject => return false,
+            Destination::Workflow(id) => workflow = id,
+        }
+    }
+    panic!("part {:?} goes round a cycle of workflows forever, through {}", part, workflow);
+}
+
+#[derive(clap::ValueEnum, PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub enum Engine {
+    /// compiled if the input was known at build time, interpreted otherwise
+    #[default]
+    Auto,
+    Compiled,
+    Interpreted,
+}
+
+/// name of the generated functions for <input>
+fn compiled_prefix(input: &str) -> String {
+    return format!("_{:016x}", fingerprint(input));
+}
+
+/// with an explicit --engine, how long evaluating the parts took
+fn report_timing(engine: Engine, start: Instant) {
+    if engine != Engine::Auto {
+        eprintln!("{:?} workflows took {:?}", engine, start.elapsed());
+    }
+}
 
 pub fn p1(input: String) {
-    // the workflows are generated at build time (not very pretty impl but conceptually I think it's fine)
-    let mut hasher = DefaultHasher::new();
-    input.hash(&mut hasher);
-    let f_prefix = format!("_{:x}", hasher.finish());
-    
+    let engine = crate::args().engine;
     let index = get_index();
-    
-    let (get_parts, f_in) = index[&f_prefix];
-    
-    println!("{}", get_parts().into_iter().map(|part| {
-        if f_in(part[0], part[1], part[2], part[3]) {
-            (part[0] + part[1] + part[2] + part[3]) as u64
-        }
-        else { 0 }
-    }).sum::<u64>());
-}
\ No newline at end of file
+    // the workflows are compiled at build time (not very pretty impl but conceptually I think it's fine)
+    let compiled = index.get(&compiled_prefix(&input));
+    if engine == Engine::Compiled && compiled.is_none() {
+        panic!("no compiled workflows for this input, it was not in input/ at build time");
+    }
+
+    let args = crate::args();
+    if args.report || args.workflow_dot.is_some() {
+        inspect_workflows(&parse_program(&input).expect("Unable to parse workflows").0);
+    }
+
+    let sum = match compiled {
+        Some((get_parts, f_in)) if engine != Engine::Interpreted => {
+            let parts = get_parts();
+            let start = Instant::now();
+            let sum = parts
+                .iter()
+                .filter(|part| f_in(part[0], part[1], part[2], part[3]))
+                .map(|part| part.iter().map(|r| *r as u64).sum::<u64>())
+                .sum::<u64>();
+            report_timing(engine, start);
+            sum
+        }
+        _ => {
+            let (workflows, parts) = parse_program(&input).expect("Unable to parse workflows");
+            let workflows = optimize(&workflows);
+            // only the evaluation is timed, like for the compiled workflows
+            let start = Instant::now();
+            let sum = parts
+                .iter()
+                .filter(|part| is_accepted(&workflows, part))
+                .map(|part| part.iter().map(|r| *r as u64).sum::<u64>())
+                .sum::<u64>();
+            report_timing(engine, start);
+            sum
+        }
+    };
+    println!("{}", sum);
+}
+
+pub fn p2(input: String) {
+    let (workflows, _) = parse_program(&input).expect("Unable to parse workflows");
+    inspect_workflows(&workflows);
+    println!("{}", count_accepted(&optimize(&workflows), "in", [(1, 4001); 4]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn ranges_are_split_at_conditions() {
+        let input = std::fs::read_to_string("input/day19_ex").unwrap();
+        let (workflows, parts) = parse_program(&input).unwrap();
+        assert_eq!(workflows.len(), 11);
+        assert_eq!(parts[0], [787, 2655, 1222, 2876]);
+        assert_eq!(count_accepted(&workflows, "in", [(1, 4001); 4]), 167409079868000);
+        let accepted: Vec<bool> = parts.iter().map(|part| is_accepted(&workflows, part)).collect();
+        assert_eq!(accepted, Vec::from([true, false, true, false, true]));
+
+        let less = Condition { category: 1, less_than: true, value: 10 };
+        let more = Condition { category: 1, less_than: false, value: 10 };
+        let ratings = [(1, 4001), (5, 20), (1, 4001), (1, 4001)];
+        assert_eq!(split(&ratings, &less).0[1], (5, 10));
+        assert_eq!(split(&ratings, &more).0[1], (11, 20));
+        assert_eq!(split(&ratings, &more).1[1], (5, 11));
+    }
+
+    #[test]
+    fn workflow_findings() {
+        let input = "\
+in{x<10:a,x<5:R,b}
+a{m>1:A,A}
+b{s<100:R,s>4000:A,c}
+c{a>5:c,R}
+d{A}
+e{x>5:R,f}
+f{R}
+
+{x=1,m=1,a=1,s=1}
+";
+        let (workflows, _) = parse_program(input).unwrap();
+        let findings = analyze(&workflows);
+        assert_eq!(findings.unreachable, Vec::from(["d", "e", "f"]));
+        assert_eq!(findings.cycles, Vec::from([Vec::from(["c"])]));
+        assert_eq!(findings.dead_branches, Vec::from([("b".to_string(), 1), ("in".to_string(), 1)]));
+        let constant: Vec<(&str, bool)> = findings.constant.iter().map(|(id, a)| (id.as_str(), *a)).collect();
+        assert_eq!(constant, Vec::from([("a", true), ("d", true), ("e", false), ("f", false)]));
+        assert_eq!(workflows["b"][1].to_string(), "s>4000:A");
+    }
+
+    #[test]
+    fn optimized_workflows_without_fallback() {
+        let (workflows, _) = parse_program("in{x<10:foo}\nfoo{m<5:A,R}\n\n{x=1,m=1,a=1,s=1}\n").unwrap();
+        let optimized = optimize(&workflows);
+        let full = [(1, 4001); 4];
+        assert_eq!(count_accepted(&workflows, "in", full), 9 * 4 * 4000 * 4000);
+        assert_eq!(count_accepted(&optimized, "in", full), 9 * 4 * 4000 * 4000);
+        assert!(is_accepted(&optimized, &[5, 1, 1, 1]));
+        assert!(!is_accepted(&optimized, &[5, 10, 1, 1]));
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle")]
+    fn interpreted_cycles_fail() {
+        let (workflows, _) = parse_program("in{x<5:a,A}\na{x>2:in,R}\n\n{x=4,m=1,a=1,s=1}\n").unwrap();
+        is_accepted(&workflows, &[4, 1, 1, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle")]
+    fn counted_cycles_fail() {
+        let (workflows, _) = parse_program("in{x<5:a,A}\na{x>2:in,R}\n\n{x=4,m=1,a=1,s=1}\n").unwrap();
+        count_accepted(&workflows, "in", [(1, 4001); 4]);
+    }
+
+    #[test]
+    fn optimized_workflows_decide_the_same() {
+        let mut rng = StdRng::seed_from_u64(19);
+        for filename in ["input/day19_ex", "input/day19"] {
+            let input = std::fs::read_to_string(filename).unwrap();
+            let (workflows, mut parts) = parse_program(&input).unwrap();
+            let optimized = optimize(&workflows);
+            assert!(optimized.len() < workflows.len());
+
+            let full = [(1, 4001); 4];
+            assert_eq!(count_accepted(&optimized, "in", full), count_accepted(&workflows, "in", full));
+            parts.extend((0..10000).map(|_| [0; 4].map(|_| rng.gen_range(1..=4000))));
+            for part in parts {
+                assert_eq!(is_accepted(&optimized, &part), is_accepted(&workflows, &part), "{:?}", part);
+            }
+        }
+    }
+}
@@ -13,7 +13,7 @@ const REAL_POKER_RULES: bool = false;
 #[grammar = "src/puzzles/day7grammar.pest"]
 struct Day6Parser;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
 enum HandType {
     HighCard,
     Pair,
@@ -38,6 +38,8 @@ struct Hand {
     id: String,
     _cards: HashMap<char, u32>,
     hand_type: (HandType, Vec<char>, Vec<char>),
+    /// what the jokers were replaced with, if there were any
+    joker: Option<char>,
 }
 
 impl FromStr for Hand {
@@ -52,6 +54,7 @@ impl FromStr for Hand {
         let hand_type = get_jokerized_type(&card_count);
         return Ok(Hand {
             id: input.to_string(),
+            joker: get_joker_substitute(&card_count),
             _cards: card_count,
             hand_type: hand_type,
         });
@@ -109,17 +112,24 @@ fn get_type(cards: &HashMap<char, u32>) -> (HandType, Vec<char>, Vec<char>) {
     return (HandType::HighCard, res, lo);
 }
 
-fn get_jokerized_type(cards: &HashMap<char, u32>) -> (HandType, Vec<char>, Vec<char>) {
-    let base = get_type(cards);
-
+/// the card all jokers should become, None if there are no jokers
+fn get_joker_substitute(cards: &HashMap<char, u32>) -> Option<char> {
     if !cards.contains_key(&'X') {
-        return base;
+        return None;
     }
 
-    let ideal_card = *base
+    let base = get_type(cards);
+    return Some(*base
         .2.iter()
         .chain(base.1.iter())
-        .filter(|c| **c != 'X').last().unwrap_or(&'A');
+        .filter(|c| **c != 'X').last().unwrap_or(&'A'));
+}
+
+fn get_jokerized_type(cards: &HashMap<char, u32>) -> (HandType, Vec<char>, Vec<char>) {
+    let Some(ideal_card) = get_joker_substitute(cards) else {
+        return get_type(cards);
+    };
+
     let mut new_hand = cards.clone();
     *(new_hand.entry(ideal_card).or_insert(0)) += new_hand.remove(&'X').unwrap();
 
@@ -191,25 +201,140 @@ fn parse_bids(input: &str, string_op: fn(&str) -> String) -> Vec<(Hand, u32)> {
     return result;
 }
 
-fn compute_sum(input: String, string_op: fn(&str) -> String) -> u64 {
-    let mut bids = parse_bids(&input, string_op);
+/// bids sorted from weakest to strongest hand, so rank is index + 1
+fn rank_bids(input: &str, string_op: fn(&str) -> String) -> Vec<(Hand, u32)> {
+    let mut bids = parse_bids(input, string_op);
     bids.sort_by(|a, b| a.0.cmp(&b.0));
+    return bids;
+}
 
+fn compute_sum(bids: &[(Hand, u32)]) -> u64 {
     let mut sum: u64 = 0;
     for (i, (_hand, bid)) in bids.iter().enumerate() {
-        //println!("{:?}", _hand);
         sum += ((i + 1) as u64) * (*bid as u64);
     }
 
     return sum;
 }
 
+/// why <hand> ranks above <previous>: the type, or the first card that differs
+fn deciding_position(hand: &Hand, previous: &Hand) -> String {
+    if hand.hand_type.0 != previous.hand_type.0 {
+        return "type".to_string();
+    }
+    if REAL_POKER_RULES {
+        return "cards".to_string();
+    }
+    return match zip(hand.id.chars(), previous.id.chars()).position(|(s, o)| s != o) {
+        Some(pos) => format!("card {}", pos + 1),
+        None => "tie".to_string(),
+    };
+}
+
+/// print every hand in rank order along with how it was scored
+fn explain_ranking(bids: &[(Hand, u32)]) {
+    let mut previous: Option<&Hand> = None;
+    for (i, (hand, bid)) in bids.iter().enumerate() {
+        let rank = (i + 1) as u64;
+        println!(
+            "{:>5} {} {:<10} jokers: {} beats previous by: {:<6} {} * {} = {}",
+            rank,
+            hand.id.replace("X", "J"),
+            format!("{:?}", hand.hand_type.0),
+            hand.joker.map(|c| format!("J->{c}")).unwrap_or("-   ".to_string()),
+            previous.map(|p| deciding_position(hand, p)).unwrap_or("-".to_string()),
+            rank,
+            bid,
+            rank * (*bid as u64)
+        );
+        previous = Some(hand);
+    }
+}
+
+fn solve(input: String, string_op: fn(&str) -> String) {
+    let bids = rank_bids(&input, string_op);
+    if crate::args().explain {
+        explain_ranking(&bids);
+    }
+    println!("{}", compute_sum(&bids));
+}
+
 pub fn p1(input: String) {
-    println!("{}", compute_sum(input, |x| x.to_string()));
+    solve(input, |x| x.to_string());
 }
 
 
 
 pub fn p2(input: String) {
-    println!("{}", compute_sum(input, |x| x.replace("J", "X")));
+    solve(input, |x| x.replace("J", "X"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JOKER_ALPHABET: &str = "AKQT98765432X";
+
+    /// best HandType reachable by replacing each joker independently
+    fn brute_force_type(hand: &str) -> HandType {
+        let mut non_jokers: Vec<char> = hand.chars().filter(|c| *c != 'X').collect();
+        let joker_count = hand.len() - non_jokers.len();
+        let choices: Vec<char> = JOKER_ALPHABET.chars().filter(|c| *c != 'X').collect();
+
+        // assignments are order-independent, so enumerating non-decreasing index tuples is enough
+        fn explore(choices: &Vec<char>, current: &mut Vec<char>, start: usize, remaining: usize) -> HandType {
+            if remaining == 0 {
+                let mut card_count = HashMap::new();
+                current.iter().for_each(|c| {
+                    *(card_count.entry(*c).or_insert(0)) += 1;
+                });
+                return get_type(&card_count).0;
+            }
+            let mut best = HandType::HighCard;
+            for i in start..choices.len() {
+                current.push(choices[i]);
+                best = best.max(explore(choices, current, i, remaining - 1));
+                current.pop();
+            }
+            return best;
+        }
+
+        return explore(&choices, &mut non_jokers, 0, joker_count);
+    }
+
+    /// every 5-card hand where the joker heuristic does worse than brute force
+    fn find_joker_counterexamples() -> Vec<(String, HandType, HandType)> {
+        let alphabet: Vec<char> = JOKER_ALPHABET.chars().collect();
+        let mut counterexamples = Vec::new();
+        // hand types only depend on the multiset of cards
+        let mut verdicts: HashMap<Vec<char>, (HandType, HandType)> = HashMap::new();
+        for n in 0..alphabet.len().pow(5) {
+            let hand: String = (0..5)
+                .map(|pos| alphabet[(n / alphabet.len().pow(pos)) % alphabet.len()])
+                .collect();
+            if !hand.contains('X') {
+                continue; // the heuristic is the identity here
+            }
+            let mut key: Vec<char> = hand.chars().collect();
+            key.sort_unstable();
+            let (heuristic, best) = verdicts
+                .entry(key)
+                .or_insert_with(|| (Hand::from_str(&hand).unwrap().hand_type.0, brute_force_type(&hand)));
+            if heuristic != best {
+                counterexamples.push((hand, *heuristic, *best));
+            }
+        }
+        return counterexamples;
+    }
+
+    #[test]
+    fn joker_heuristic_is_optimal() {
+        let counterexamples = find_joker_counterexamples();
+        assert!(
+            counterexamples.is_empty(),
+            "{} counterexamples (hand, heuristic, best), e.g. {:?}",
+            counterexamples.len(),
+            &counterexamples[..counterexamples.len().min(10)]
+        );
+    }
 }
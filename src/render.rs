@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+
+use terminal_size::{terminal_size, Height, Width};
+
+use crate::animation::{Frame, Palette};
+
+const RESET: &str = "\x1b[0m";
+const REVERSE: &str = "\x1b[7m";
+const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J";
+
+/// draws grids in the terminal, coloured and cropped to fit
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    palette: Palette,
+    /// (x, y) of cells to draw in reverse video
+    highlights: HashSet<(usize, usize)>,
+    /// top left (x, y) cell of the viewport
+    origin: (usize, usize),
+    /// (width, height) of the viewport, in cells
+    size: (usize, usize),
+    /// redraw in place with a pause between frames, instead of printing them one after the other
+    live: bool,
+    delay: Duration,
+}
+
+/// "3,4;10,2" is the cells (3, 4) and (10, 2)
+pub fn parse_coordinates(input: &str) -> Result<Vec<(usize, usize)>, String> {
+    input
+        .split(";")
+        .filter(|c| !c.is_empty())
+        .map(|c| {
+            let parsed = c.split_once(",").and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)));
+            parsed.ok_or(format!("bad coordinates '{}', expected x,y", c))
+        })
+        .collect()
+}
+
+impl Renderer {
+    /// with the options given on the command line
+    pub fn from_args() -> Renderer {
+        let args = crate::args();
+        let palette = match &args.palette {
+            Some(spec) => Palette::parse(spec).unwrap_or_else(|e| panic!("{}", e)),
+            None => Palette::default(),
+        };
+        let parse = |input: &Option<String>| {
+            parse_coordinates(input.as_deref().unwrap_or("")).unwrap_or_else(|e| panic!("{}", e))
+        };
+        // keep a line for the viewport status
+        let terminal = terminal_size().map_or((80, 24), |(Width(w), Height(h))| (w as usize, (h as usize).saturating_sub(1).max(1)));
+        return Renderer {
+            palette,
+            highlights: parse(&args.highlight).into_iter().collect(),
+            origin: parse(&args.viewport).first().copied().unwrap_or((0, 0)),
+            size: parse(&args.viewport_size).first().copied().unwrap_or(terminal),
+            live: args.live,
+            delay: Duration::from_millis(args.frame_delay as u64 * 10),
+        };
+    }
+
+    pub fn render(&self, frame: &Frame) -> String {
+        let height = frame.len();
+        let width = frame.iter().map(|line| line.len()).max().unwrap_or(0);
+        // scrolling past the end still shows the last screenful
+        let x0 = self.origin.0.min(width.saturating_sub(self.size.0));
+        let y0 = self.origin.1.min(height.saturating_sub(self.size.1));
+
+        let mut result = String::new();
+        for (y, line) in frame.iter().enumerate().skip(y0).take(self.size.1) {
+            for (x, c) in line.iter().enumerate().skip(x0).take(self.size.0) {
+                let [r, g, b] = self.palette.color(*c);
+                if self.highlights.contains(&(x, y)) {
+                    result.push_str(REVERSE);
+                }
+                result.push_str(&format!("\x1b[38;2;{};{};{}m{}{}", r, g, b, c, RESET));
+            }
+            result.push('\n');
+        }
+        if width > self.size.0 || height > self.size.1 {
+            result.push_str(&format!(
+                "columns {}-{} of {}, rows {}-{} of {}\n",
+                x0,
+                (x0 + self.size.0).min(width).saturating_sub(1),
+                width,
+                y0,
+                (y0 + self.size.1).min(height).saturating_sub(1),
+                height
+            ));
+        }
+        return result;
+    }
+
+    pub fn show(&self, frame: &Frame) {
+        if self.live {
+            print!("{}", CLEAR_SCREEN);
+        }
+        print!("{}", self.render(frame));
+        if self.live {
+            thread::sleep(self.delay);
+        }
+    }
+
+    /// every frame if live, only the last one otherwise
+    pub fn show_all(&self, frames: &[Frame]) {
+        let shown = if self.live { frames } else { &frames[frames.len().saturating_sub(1)..] };
+        for frame in shown {
+            self.show(frame);
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.live
+    }
+}
+
+/// text lines as a frame, for things that are not really grids
+pub fn text_frame(text: &str) -> Frame {
+    text.lines().map(|line| line.chars().collect()).collect()
+}
@@ -0,0 +1,332 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use pest::{iterators::Pair, Parser};
+use pest_derive::Parser;
+
+/*
+day 19 workflows as data: parsed from the input, simplified, then either interpreted
+or turned into Rust functions by build.rs, which includes this file
+*/
+
+#[derive(Parser)]
+#[grammar = "src/puzzles/day19grammar.pest"]
+struct Day19Parser;
+
+pub const CATEGORIES: [char; 4] = ['x', 'm', 'a', 's'];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    /// index in CATEGORIES
+    pub category: usize,
+    pub less_than: bool,
+    pub value: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    Accept,
+    Reject,
+    Workflow(String),
+}
+
+/// one rule of a workflow; the grammar already calls them Rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Branch {
+    pub condition: Option<Condition>,
+    pub destination: Destination,
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let comparison = if self.less_than { '<' } else { '>' };
+        write!(f, "{}{}{}", CATEGORIES[self.category], comparison, self.value)
+    }
+}
+
+impl Display for Destination {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Destination::Accept => write!(f, "A"),
+            Destination::Reject => write!(f, "R"),
+            Destination::Workflow(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl Display for Branch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match &self.condition {
+            Some(condition) => write!(f, "{}:{}", condition, self.destination),
+            None => write!(f, "{}", self.destination),
+        }
+    }
+}
+
+/// workflows by id
+pub type Workflows = HashMap<String, Vec<Branch>>;
+
+fn parse_condition(p: Pair<'_, Rule>) -> Condition {
+    let text = p.as_str();
+    let category = CATEGORIES.iter().position(|c| text.starts_with(*c)).unwrap();
+    return Condition {
+        category,
+        less_than: &text[1..2] == "<",
+        value: text[2..].parse().unwrap(),
+    };
+}
+
+fn parse_branch(p: Pair<'_, Rule>) -> Branch {
+    let mut condition = None;
+    let mut destination = Destination::Reject;
+    for token in p.into_inner() {
+        match token.as_rule() {
+            Rule::Condition => condition = Some(parse_condition(token)),
+            Rule::Destination => {
+                destination = match token.as_str() {
+                    "A" => Destination::Accept,
+                    "R" => Destination::Reject,
+                    id => Destination::Workflow(id.to_string()),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+    return Branch { condition, destination };
+}
+
+/// (workflows, parts)
+pub fn parse_program(input: &str) -> Result<(Workflows, Vec<[u32; 4]>), String> {
+    let mut program = Day19Parser::parse(Rule::Program, input)
+        .map_err(|e| e.to_string())?
+        .next()
+        .unwrap()
+        .into_inner();
+
+    let workflows = program
+        .next()
+        .unwrap()
+        .into_inner()
+        .map(|workflow| {
+            let mut tokens = workflow.into_inner();
+            let id = tokens.next().unwrap().as_str().to_string();
+            (id, tokens.map(parse_branch).collect())
+        })
+        .collect();
+
+    let parts = program
+        .next()
+        .unwrap()
+        .into_inner()
+        .map(|part| {
+            let mut ratings = [0; 4];
+            for characteristic in part.into_inner() {
+                let text = characteristic.as_str();
+                let category = CATEGORIES.iter().position(|c| text.starts_with(*c)).unwrap();
+                ratings[category] = text[2..].parse().unwrap();
+            }
+            ratings
+        })
+        .collect();
+
+    return Ok((workflows, parts));
+}
+
+/// half-open range of ratings for each category
+pub type RatingBox = [(u32, u32); 4];
+
+pub fn volume(ratings: &RatingBox) -> u64 {
+    ratings.iter().map(|(lo, hi)| hi.saturating_sub(*lo) as u64).product()
+}
+
+/// (matching, not matching) parts of <ratings>, either may be empty
+pub fn split(ratings: &RatingBox, condition: &Condition) -> (RatingBox, RatingBox) {
+    let (lo, hi) = ratings[condition.category];
+    // the first value that goes to the upper half
+    let cut = if condition.less_than { condition.value } else { condition.value + 1 };
+    let lower = (lo, hi.min(cut));
+    let upper = (lo.max(cut), hi);
+    let (mut matching, mut rest) = (*ratings, *ratings);
+    if condition.less_than {
+        (matching[condition.category], rest[condition.category]) = (lower, upper);
+    } else {
+        (matching[condition.category], rest[condition.category]) = (upper, lower);
+    }
+    return (matching, rest);
+}
+
+/// rules of <branches> that no part can match, whatever it is
+pub fn dead_branches(branches: &[Branch]) -> HashSet<usize> {
+    let mut rest = [(1, 4001); 4];
+    let mut dead = HashSet::new();
+    for (i, branch) in branches.iter().enumerate() {
+        let matching = match &branch.condition {
+            Some(condition) => {
+                let (matching, not_matching) = split(&rest, condition);
+                rest = not_matching;
+                matching
+            }
+            None => std::mem::replace(&mut rest, [(0, 0); 4]),
+        };
+        if volume(&matching) == 0 {
+            dead.insert(i);
+        }
+    }
+    return dead;
+}
+
+/// <branches> without the rules no part can get to, the last one that can fire made unconditional
+/// if parts can never get past it
+fn without_dead_branches(branches: &[Branch]) -> Vec<Branch> {
+    let dead = dead_branches(branches);
+    let mut kept: Vec<Branch> = branches
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !dead.contains(i))
+        .map(|(_, branch)| branch.clone())
+        .collect();
+    // an unconditional rule is only dead when nothing is left for it
+    let nothing_left = branches.iter().enumerate().any(|(i, branch)| branch.condition.is_none() && dead.contains(&i));
+    if let Some(last) = kept.last_mut().filter(|_| nothing_left) {
+        last.condition = None;
+    }
+    return kept;
+}
+
+fn drop_dead_branches(workflows: &mut Workflows) -> bool {
+    let mut changed = false;
+    for branches in workflows.values_mut() {
+        let kept = without_dead_branches(branches);
+        if kept != *branches {
+            *branches = kept;
+            changed = true;
+        }
+    }
+    return changed;
+}
+
+/// "m>1548:A,A" is just "A"
+fn merge_same_destinations(workflows: &mut Workflows) -> bool {
+    let mut changed = false;
+    for branches in workflows.values_mut() {
+        while let [.., before, last] = branches.as_slice() {
+            if last.condition.is_some() || before.destination != last.destination {
+                break;
+            }
+            branches.remove(branches.len() - 2);
+            changed = true;
+        }
+    }
+    return changed;
+}
+
+/// workflows that are only "A" or "R" are replaced by that wherever they are used
+fn replace_constants(workflows: &mut Workflows) -> bool {
+    let constants: HashMap<String, Destination> = workflows
+        .iter()
+        .filter(|(id, _)| *id != "in")
+        .filter_map(|(id, branches)| match branches.as_slice() {
+            [Branch { condition: None, destination: d @ (Destination::Accept | Destination::Reject) }] => {
+                Some((id.clone(), d.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    if constants.is_empty() {
+        return false;
+    }
+    for id in constants.keys() {
+        workflows.remove(id);
+    }
+    for branch in workflows.values_mut().flatten() {
+        if let Destination::Workflow(next) = &branch.destination {
+            if let Some(destination) = constants.get(next) {
+                branch.destination = destination.clone();
+            }
+        }
+    }
+    return true;
+}
+
+/// a workflow used only as the unconditional fallback of another one is pasted in its place
+fn inline_single_use(workflows: &mut Workflows) -> bool {
+    let mut uses = HashMap::<&str, Vec<(&str, usize)>>::new();
+    for (id, branches) in workflows.iter() {
+        for (i, branch) in branches.iter().enumerate() {
+            if let Destination::Workflow(next) = &branch.destination {
+                uses.entry(next).or_default().push((id, i));
+            }
+        }
+    }
+    let mut candidates: Vec<(String, String)> = uses
+        .iter()
+        .filter(|(id, _)| **id != "in" && workflows.contains_key(**id))
+        .filter_map(|(id, used_by)| match used_by.as_slice() {
+            // a last rule with a condition is not a fallback, its parts must still match it
+            [(parent, i)]
+                if parent != id
+                    && *i == workflows[*parent].len() - 1
+                    && workflows[*parent][*i].condition.is_none() =>
+            {
+                Some((id.to_string(), parent.to_string()))
+            }
+            _ => None,
+        })
+        .collect();
+    // one at a time, the next ones may have changed; sorted so that the result does not depend on the HashMap
+    candidates.sort();
+    let Some((id, parent)) = candidates.into_iter().next() else {
+        return false;
+    };
+    let inlined = workflows.remove(&id).unwrap();
+    let branches = workflows.get_mut(&parent).unwrap();
+    branches.pop();
+    branches.extend(inlined);
+    return true;
+}
+
+/// same decisions as <workflows>, with fewer rules and workflows
+pub fn optimize(workflows: &Workflows) -> Workflows {
+    let mut optimized = workflows.clone();
+    // every pass can open opportunities for the others
+    while drop_dead_branches(&mut optimized)
+        | merge_same_destinations(&mut optimized)
+        | replace_constants(&mut optimized)
+        | inline_single_use(&mut optimized)
+    {}
+    return optimized;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_workflows(workflows: &str) -> Workflows {
+        parse_program(&format!("{}\n{{x=1,m=1,a=1,s=1}}\n", workflows)).unwrap().0
+    }
+
+    #[test]
+    fn passes() {
+        let original = parse_workflows(
+            "\
+in{x<10:lnx,x<5:R,x>4000:R,qs}
+lnx{m>1548:A,A}
+gd{a>3333:R,R}
+qs{s>3448:gd,pv}
+pv{a>1716:R,x<2000:px,px}
+px{x<20:A,A}
+",
+        );
+        let optimized = optimize(&original);
+        let expected = parse_workflows(
+            "\
+in{x<10:A,s>3448:R,a>1716:R,A}
+",
+        );
+        assert_eq!(optimized, expected);
+
+        // without a fallback rule, foo is only reached by parts that match x<10
+        let no_fallback = parse_workflows("in{x<10:foo}\nfoo{m<5:A,R}\n");
+        assert_eq!(optimize(&no_fallback), no_fallback);
+    }
+}